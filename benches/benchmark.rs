@@ -0,0 +1,177 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::thread;
+
+use astar::astar::{dpa, hda, seq};
+use astar::utils::helpers::{man_dist, misplaced};
+use astar::utils::heuristic::{Heuristic, ManDist};
+use astar::utils::structs::{AZHasher, Grid, Node, ZHasher};
+
+// Fixed instances, the same on every machine and run.
+// (name, rows, cols, end seed, #random moves, walk seed)
+const INSTANCES: [(&str, i32, i32, u64, i32, u64); 4] = [
+    ("4x4", 4, 4, 69, 60, 7),
+    ("5x5", 5, 5, 69, 50, 11),
+    ("3x4", 3, 4, 69, 60, 7),
+    ("2x8", 2, 8, 69, 60, 7),
+];
+const THREADS: [usize; 4] = [1, 2, 4, 8];
+const TEMPS: [f32; 4] = [0.2, 0.4, 0.6, 0.8];
+
+type HFunc = fn(&Grid, &Grid) -> i32;
+const HEURISTICS: [(&str, HFunc); 2] = [("man_dist", man_dist), ("misplaced", misplaced)];
+
+fn instances() -> Vec<(&'static str, Grid, Grid)> {
+    INSTANCES
+        .iter()
+        .map(|&(name, rows, cols, end_seed, n, walk_seed)| {
+            let end = Grid::rand_rect_with_seed(rows, cols, end_seed);
+            let start = end.rand_actions_with_seed(n, walk_seed);
+            (name, start, end)
+        })
+        .collect()
+}
+
+fn bench_seq(c: &mut Criterion) {
+    let mut group = c.benchmark_group("seq");
+    group.sample_size(10);
+    for (name, start, end) in instances() {
+        for (h_name, h_func) in HEURISTICS {
+            let id = BenchmarkId::new(h_name, name);
+            group.bench_function(id, |b| b.iter(|| seq::astar(&start, &end, h_func)));
+        }
+    }
+    group.finish();
+}
+
+fn bench_dpa(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dpa");
+    group.sample_size(10);
+    let (name, start, end) = instances().remove(0);
+    for num_threads in THREADS {
+        for temp in TEMPS {
+            let id = BenchmarkId::new(format!("{}_t{}", name, num_threads), temp);
+            group.bench_function(id, |b| {
+                b.iter(|| dpa::astar(&start, &end, man_dist, num_threads, temp))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_hda(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hda");
+    group.sample_size(10);
+    for (name, start, end) in instances() {
+        for num_threads in THREADS {
+            let hasher = ZHasher::new(start.rows, start.cols);
+            let id = BenchmarkId::new(format!("{}_zobrist", name), num_threads);
+            group.bench_function(id, |b| {
+                b.iter(|| hda::astar(&start, &end, man_dist, num_threads, hasher.clone()))
+            });
+
+            let abstract_hasher = AZHasher::new(start.rows, start.cols);
+            let id = BenchmarkId::new(format!("{}_abstract", name), num_threads);
+            group.bench_function(id, |b| {
+                b.iter(|| {
+                    let (hasher, abstract_hasher) = (hasher.clone(), abstract_hasher.clone());
+                    hda::astar_abstract(
+                        &start,
+                        &end,
+                        man_dist,
+                        num_threads,
+                        hasher,
+                        abstract_hasher,
+                    )
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_heuristics(c: &mut Criterion) {
+    let mut group = c.benchmark_group("heuristic");
+    for (name, start, end) in instances() {
+        for (h_name, h_func) in HEURISTICS {
+            group.bench_with_input(BenchmarkId::new(h_name, name), &start, |b, s| {
+                b.iter(|| h_func(black_box(s), &end))
+            });
+        }
+        let table = ManDist::new(&end);
+        group.bench_with_input(
+            BenchmarkId::new("weighted_man_dist_table", name),
+            &start,
+            |b, s| b.iter(|| table.h(black_box(s), &end)),
+        );
+    }
+    group.finish();
+}
+
+fn bench_grid(c: &mut Criterion) {
+    let mut group = c.benchmark_group("grid");
+    for (name, start, _) in instances() {
+        group.bench_with_input(BenchmarkId::new("expand", name), &start, |b, s| {
+            b.iter(|| black_box(s).expand())
+        });
+
+        let hasher = ZHasher::new(start.rows, start.cols);
+        group.bench_with_input(BenchmarkId::new("hash_zobrist", name), &start, |b, s| {
+            b.iter(|| black_box(s).hash_with(&hasher))
+        });
+
+        let hasher = AZHasher::new(start.rows, start.cols);
+        group.bench_with_input(BenchmarkId::new("hash_abstract", name), &start, |b, s| {
+            b.iter(|| black_box(s).hash_with(&hasher))
+        });
+    }
+    group.finish();
+}
+
+// Throughput of the crossbeam channels the parallel searches send nodes over.
+fn bench_channel(c: &mut Criterion) {
+    let batch = 1000;
+    let (_, start, end) = instances().remove(0);
+    let mut node = Node::new(start);
+    node.calc_cost(&end, &man_dist);
+
+    let mut group = c.benchmark_group("channel");
+    group.throughput(Throughput::Elements(batch as u64));
+    group.bench_function("same_thread", |b| {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        b.iter(|| {
+            for _ in 0..batch {
+                tx.send(node.clone()).unwrap();
+            }
+            for _ in 0..batch {
+                black_box(rx.recv().unwrap());
+            }
+        })
+    });
+    group.bench_function("cross_thread", |b| {
+        b.iter(|| {
+            let (tx, rx) = crossbeam::channel::unbounded();
+            let n = node.clone();
+            let h = thread::spawn(move || {
+                for _ in 0..batch {
+                    tx.send(n.clone()).unwrap();
+                }
+            });
+            for _ in 0..batch {
+                black_box(rx.recv().unwrap());
+            }
+            h.join().unwrap();
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_seq,
+    bench_dpa,
+    bench_hda,
+    bench_heuristics,
+    bench_grid,
+    bench_channel
+);
+criterion_main!(benches);
@@ -0,0 +1,895 @@
+// HDA* over processes instead of threads. A worker process owns the
+// states whose hash modulo the number of workers is its rank, it
+// expands its own states and sends the other successors to their
+// owners over sockets. A coordinator starts the workers, tells them
+// the instance and each other's addresses and detects termination.
+// - A worker that expands a goal cheaper than the incumbent sends its
+//   cost to all other workers and the coordinator. Nodes with an f that
+//   is not below the incumbent are not expanded.
+// - A worker is idle if it has no node below the incumbent. The
+//   coordinator probes the workers in rounds, each answers whether it
+//   is idle and how many nodes it has sent and received. Only receiving
+//   a node makes a worker busy again, so once two rounds in a row find
+//   every worker idle, as many nodes received as sent and the same
+//   counts, no node is on its way and the incumbent is optimal.
+// Nodes go over the wire as g, h, the hash and the packed state, the
+// instance as encoded grids.
+use std::collections::BinaryHeap;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::process::Child;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam::channel::{unbounded, Receiver, Sender};
+
+use crate::error::{Error, Result};
+use crate::utils::helpers::expand_hashed;
+use crate::utils::heuristic::Heuristic;
+use crate::utils::net::{put_str, read_frame, write_frame, Addr, Fields, Listener, Stream};
+use crate::utils::structs::{
+    AnyHasher, Grid, KeyWidth, Keys, Limit, Log, Node, State, StateHasher,
+};
+use crate::utils::table::StateMap;
+
+// Time between two probes of the coordinator.
+const PROBE_INTERVAL: Duration = Duration::from_millis(2);
+// Time the workers have to connect to the coordinator.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// Expansions between flushes of the nodes for other workers.
+const FLUSH_INTERVAL: u32 = 64;
+
+const HELLO: u8 = 0;
+const SETUP: u8 = 1;
+const PEER: u8 = 2;
+const READY: u8 = 3;
+const START: u8 = 4;
+const NODE: u8 = 5;
+const INCUMBENT: u8 = 6;
+const PROBE: u8 = 7;
+const REPORT: u8 = 8;
+const STOP: u8 = 9;
+const DONE: u8 = 10;
+
+#[derive(Debug)]
+enum Msg {
+    // worker to coordinator, where the worker listens for peers
+    Hello(Addr),
+    // coordinator to worker, peers[rank] is the worker itself
+    Setup {
+        rank: usize,
+        peers: Vec<Addr>,
+        start: Grid,
+        goal: Grid,
+        keys: Keys,
+    },
+    // first message on a connection between workers
+    Peer(usize),
+    // worker to coordinator, connected to all peers
+    Ready,
+    Start,
+    Node(Node),
+    Incumbent(i32),
+    Probe(u32),
+    Report {
+        round: u32,
+        idle: bool,
+        sent: u64,
+        recv: u64,
+    },
+    Stop,
+    Done(Log),
+}
+
+impl Msg {
+    fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        let mut body = vec![];
+        let tag = match self {
+            Msg::Hello(addr) => {
+                put_str(&mut body, &addr.to_string());
+                HELLO
+            }
+            Msg::Setup {
+                rank,
+                peers,
+                start,
+                goal,
+                keys,
+            } => {
+                body.extend((*rank as u32).to_be_bytes());
+                body.extend(keys.width.bits().to_be_bytes());
+                body.push(keys.seed.is_some() as u8);
+                body.extend(keys.seed.unwrap_or(0).to_be_bytes());
+                body.extend((peers.len() as u32).to_be_bytes());
+                for peer in peers {
+                    put_str(&mut body, &peer.to_string());
+                }
+                start.encode(&mut body);
+                goal.encode(&mut body);
+                SETUP
+            }
+            Msg::Peer(rank) => {
+                body.extend((*rank as u32).to_be_bytes());
+                PEER
+            }
+            Msg::Ready => READY,
+            Msg::Start => START,
+            Msg::Node(node) => {
+                body.extend(node.g.to_be_bytes());
+                body.extend(node.h.to_be_bytes());
+                body.extend(node.hash.to_be_bytes());
+                node.state.pack(&mut body);
+                NODE
+            }
+            Msg::Incumbent(cost) => {
+                body.extend(cost.to_be_bytes());
+                INCUMBENT
+            }
+            Msg::Probe(round) => {
+                body.extend(round.to_be_bytes());
+                PROBE
+            }
+            Msg::Report {
+                round,
+                idle,
+                sent,
+                recv,
+            } => {
+                body.extend(round.to_be_bytes());
+                body.push(*idle as u8);
+                body.extend(sent.to_be_bytes());
+                body.extend(recv.to_be_bytes());
+                REPORT
+            }
+            Msg::Stop => STOP,
+            Msg::Done(log) => {
+                for n in [
+                    log.iter_cnt,
+                    log.node_cnt,
+                    log.abort_cnt,
+                    log.exp_cnt,
+                    log.gen_cnt,
+                ] {
+                    body.extend(n.to_be_bytes());
+                }
+                DONE
+            }
+        };
+        write_frame(w, tag, &body)
+    }
+
+    // like is the goal, nodes can only be read once it is known.
+    // None if the stream ended.
+    fn read(stream: &mut Stream, like: Option<&Grid>) -> Result<Option<Msg>> {
+        let (tag, body) = match read_frame(stream)? {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        let mut f = Fields::new(&body);
+        let msg = match tag {
+            HELLO => Msg::Hello(f.str()?.parse()?),
+            SETUP => {
+                let rank = f.u32()? as usize;
+                let bits = f.u32()?;
+                let width = KeyWidth::from_bits(bits)
+                    .ok_or_else(|| Error::Protocol(format!("{} bit keys", bits)))?;
+                let (has_seed, seed) = (f.u8()? != 0, f.u64()?);
+                let keys = Keys {
+                    width,
+                    seed: has_seed.then_some(seed),
+                };
+                let peers = (0..f.u32()?)
+                    .map(|_| f.str()?.parse())
+                    .collect::<Result<Vec<Addr>>>()?;
+                let rest = f.rest();
+                let (start, used) = Grid::decode(rest)?;
+                let (goal, _) = Grid::decode(&rest[used..])?;
+                Msg::Setup {
+                    rank,
+                    peers,
+                    start,
+                    goal,
+                    keys,
+                }
+            }
+            PEER => Msg::Peer(f.u32()? as usize),
+            READY => Msg::Ready,
+            START => Msg::Start,
+            NODE => {
+                let like = like.ok_or_else(|| Error::Protocol("node before setup".to_string()))?;
+                let (g, h, hash) = (f.i32()?, f.i32()?, f.u64()?);
+                let state = Grid::unpack(f.take(like.packed_len())?, like);
+                Msg::Node(Node {
+                    state,
+                    f: g + h,
+                    g,
+                    h,
+                    hash,
+                    abstract_hash: 0,
+                })
+            }
+            INCUMBENT => Msg::Incumbent(f.i32()?),
+            PROBE => Msg::Probe(f.u32()?),
+            REPORT => Msg::Report {
+                round: f.u32()?,
+                idle: f.u8()? != 0,
+                sent: f.u64()?,
+                recv: f.u64()?,
+            },
+            STOP => Msg::Stop,
+            DONE => Msg::Done(Log {
+                iter_cnt: f.i32()?,
+                node_cnt: f.i32()?,
+                abort_cnt: f.i32()?,
+                exp_cnt: f.i32()?,
+                gen_cnt: f.i32()?,
+            }),
+            tag => return Err(Error::Protocol(format!("unknown tag {}", tag))),
+        };
+        Ok(Some(msg))
+    }
+}
+
+fn unexpected(msg: &Msg) -> Error {
+    Error::Protocol(format!("unexpected message {:?}", msg))
+}
+
+fn expect(stream: &mut Stream, like: Option<&Grid>) -> Result<Msg> {
+    Msg::read(stream, like)?.ok_or_else(|| Error::Protocol("connection closed".to_string()))
+}
+
+// Reads messages from stream on a thread of its own. The end of the
+// stream is sent as an error if closed is Some, else dropped.
+fn forward<T: Copy + Send + 'static>(
+    mut stream: Stream,
+    like: Grid,
+    tx: Sender<(T, Result<Msg>)>,
+    id: T,
+    closed: Option<String>,
+) {
+    thread::spawn(move || loop {
+        match Msg::read(&mut stream, Some(&like)) {
+            Ok(Some(msg)) => {
+                if tx.send((id, Ok(msg))).is_err() {
+                    return;
+                }
+            }
+            Ok(None) => {
+                if let Some(msg) = closed {
+                    let _ = tx.send((id, Err(Error::Protocol(msg))));
+                }
+                return;
+            }
+            Err(err) => {
+                let _ = tx.send((id, Err(err)));
+                return;
+            }
+        }
+    });
+}
+
+// A worker started for coordinate, a process running work.
+pub trait Spawned {
+    // An error once it exited.
+    fn check(&mut self) -> Result<()>;
+    // Waits for it to exit, an error if it failed.
+    fn wait(&mut self) -> Result<()>;
+    // Stops it if it still runs.
+    fn kill(&mut self);
+}
+
+impl Spawned for Child {
+    fn check(&mut self) -> Result<()> {
+        match self.try_wait()? {
+            Some(status) => Err(Error::Protocol(format!("worker exited with {}", status))),
+            None => Ok(()),
+        }
+    }
+
+    fn wait(&mut self) -> Result<()> {
+        let status = Child::wait(self)?;
+        if !status.success() {
+            return Err(Error::Protocol(format!("worker exited with {}", status)));
+        }
+        Ok(())
+    }
+
+    fn kill(&mut self) {
+        let _ = Child::kill(self);
+        let _ = Child::wait(self);
+    }
+}
+
+// The workers of one search, killed if still running when dropped.
+struct Workers<W: Spawned>(Vec<W>);
+
+impl<W: Spawned> Workers<W> {
+    fn check(&mut self) -> Result<()> {
+        for worker in self.0.iter_mut() {
+            worker.check()?;
+        }
+        Ok(())
+    }
+
+    fn wait(mut self) -> Result<()> {
+        for mut worker in self.0.drain(..) {
+            worker.wait()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Spawned> Drop for Workers<W> {
+    fn drop(&mut self) {
+        for worker in self.0.iter_mut() {
+            worker.kill();
+        }
+    }
+}
+
+// Solves an instance on num_workers workers started by spawn, which
+// gets the address of the coordinator to pass to work. The coordinator
+// listens on addr, port 0 picks a free port. The workers hash with
+// the given keys.
+// Only the timeout of the limit is checked. None is returned if it ran
+// out first, also if a path was found by then, as its cost is not
+// proven optimal. One log per worker.
+pub fn coordinate<W: Spawned>(
+    init_state: &Grid,
+    end_state: &Grid,
+    num_workers: usize,
+    addr: &Addr,
+    mut spawn: impl FnMut(&Addr) -> std::io::Result<W>,
+    keys: Keys,
+    limit: &Limit,
+) -> Result<(Option<Node>, Vec<Log>)> {
+    init_state.check_pair(end_state)?;
+    let num_workers = num_workers.max(1);
+    let listener = Listener::bind(addr)?;
+    let addr = listener.local_addr()?;
+    let mut workers = Workers(vec![]);
+    for _ in 0..num_workers {
+        workers.0.push(spawn(&addr)?);
+    }
+
+    // Connections in the order the workers connect, which is their rank.
+    listener.set_nonblocking(true)?;
+    let deadline = Instant::now() + CONNECT_TIMEOUT;
+    let mut streams = vec![];
+    while streams.len() < num_workers {
+        match listener.accept() {
+            Ok(stream) => streams.push(stream),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                workers.check()?;
+                if Instant::now() >= deadline {
+                    return Err(Error::Protocol("workers did not connect".to_string()));
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let mut peers = vec![];
+    for stream in streams.iter_mut() {
+        match expect(stream, None)? {
+            Msg::Hello(peer) => peers.push(peer),
+            msg => return Err(unexpected(&msg)),
+        }
+    }
+    for (rank, stream) in streams.iter_mut().enumerate() {
+        Msg::Setup {
+            rank,
+            peers: peers.clone(),
+            start: init_state.clone(),
+            goal: end_state.clone(),
+            keys,
+        }
+        .write(stream)?;
+    }
+    for stream in streams.iter_mut() {
+        match expect(stream, None)? {
+            Msg::Ready => {}
+            msg => return Err(unexpected(&msg)),
+        }
+    }
+
+    let (tx, rx) = unbounded();
+    for (rank, stream) in streams.iter().enumerate() {
+        let closed = format!("worker {} closed its connection", rank);
+        forward(
+            stream.try_clone()?,
+            end_state.clone(),
+            tx.clone(),
+            rank,
+            Some(closed),
+        );
+    }
+    broadcast(&mut streams, &Msg::Start)?;
+
+    let mut best = i32::MAX;
+    let mut last = None;
+    let mut round = 0;
+    let mut stopped = false;
+    loop {
+        thread::sleep(PROBE_INTERVAL);
+        if limit.reached() {
+            stopped = true;
+            break;
+        }
+        round += 1;
+        broadcast(&mut streams, &Msg::Probe(round))?;
+        let (mut reports, mut all_idle, mut sent, mut recv) = (0, true, 0, 0);
+        while reports < num_workers {
+            match recv_from(&rx)?.1? {
+                Msg::Incumbent(cost) => best = best.min(cost),
+                Msg::Report {
+                    round: r,
+                    idle,
+                    sent: s,
+                    recv: n,
+                } if r == round => {
+                    reports += 1;
+                    all_idle &= idle;
+                    sent += s;
+                    recv += n;
+                }
+                msg => return Err(unexpected(&msg)),
+            }
+        }
+        let quiet = all_idle && sent == recv;
+        if quiet && last == Some((sent, recv)) {
+            break;
+        }
+        last = quiet.then_some((sent, recv));
+    }
+
+    // A worker closes its connection after its log,
+    // which may come before the logs of others.
+    broadcast(&mut streams, &Msg::Stop)?;
+    let mut logs: Vec<Option<Log>> = vec![None; num_workers];
+    while logs.iter().any(|l| l.is_none()) {
+        let (rank, msg) = recv_from(&rx)?;
+        match msg {
+            Err(_) if logs[rank].is_some() => {}
+            Err(err) => return Err(err),
+            Ok(Msg::Incumbent(cost)) => best = best.min(cost),
+            Ok(Msg::Report { .. }) => {}
+            Ok(Msg::Done(log)) => logs[rank] = Some(log),
+            Ok(msg) => return Err(unexpected(&msg)),
+        }
+    }
+    let logs = logs.into_iter().flatten().collect();
+    workers.wait()?;
+
+    let end = (!stopped && best < i32::MAX).then(|| {
+        let mut node = Node::new(end_state.clone());
+        node.g = best;
+        node.f = best;
+        node
+    });
+    Ok((end, logs))
+}
+
+fn broadcast(streams: &mut [Stream], msg: &Msg) -> Result<()> {
+    for stream in streams.iter_mut() {
+        msg.write(stream)?;
+    }
+    Ok(())
+}
+
+fn recv_from<T>(rx: &Receiver<(T, Result<Msg>)>) -> Result<(T, Result<Msg>)> {
+    rx.recv()
+        .map_err(|_| Error::Protocol("all connections closed".to_string()))
+}
+
+// Runs one worker until the coordinator at addr stops it, with the
+// heuristic h_for builds for the goal. The worker listens for its
+// peers on listen, port 0 picks a free port, and tells them the address
+// it got, so listen must be one the other workers can reach.
+pub fn work(
+    addr: &Addr,
+    listen: &Addr,
+    h_for: impl FnOnce(&Grid) -> Result<Box<dyn Heuristic<Grid>>>,
+) -> Result<()> {
+    let mut control = Stream::connect(addr)?;
+    let listener = Listener::bind(listen)?;
+    Msg::Hello(listener.local_addr()?).write(&mut control)?;
+    let (rank, peers, start, goal, keys) = match expect(&mut control, None)? {
+        Msg::Setup {
+            rank,
+            peers,
+            start,
+            goal,
+            keys,
+        } => (rank, peers, start, goal, keys),
+        msg => return Err(unexpected(&msg)),
+    };
+
+    // Workers connect to the workers of lower rank.
+    let mut streams: Vec<Option<Stream>> = (0..peers.len()).map(|_| None).collect();
+    for (r, peer) in peers.iter().enumerate().take(rank) {
+        let mut stream = Stream::connect(peer)?;
+        Msg::Peer(rank).write(&mut stream)?;
+        streams[r] = Some(stream);
+    }
+    for _ in rank + 1..peers.len() {
+        let mut stream = listener.accept()?;
+        match expect(&mut stream, None)? {
+            Msg::Peer(r) if r > rank && r < peers.len() && streams[r].is_none() => {
+                streams[r] = Some(stream)
+            }
+            msg => return Err(unexpected(&msg)),
+        }
+    }
+    drop(listener);
+    let h_func = h_for(&goal)?;
+    Msg::Ready.write(&mut control)?;
+    match expect(&mut control, None)? {
+        Msg::Start => {}
+        msg => return Err(unexpected(&msg)),
+    }
+
+    let (tx, rx) = unbounded();
+    let mut writers = vec![];
+    for stream in streams {
+        let writer = match stream {
+            Some(stream) => {
+                forward(stream.try_clone()?, goal.clone(), tx.clone(), (), None);
+                Some(BufWriter::new(stream))
+            }
+            None => None,
+        };
+        writers.push(writer);
+    }
+    let closed = "the coordinator closed its connection".to_string();
+    forward(control.try_clone()?, goal.clone(), tx, (), Some(closed));
+
+    let mut worker = Worker {
+        goal,
+        h_func: &*h_func,
+        hasher: start.keyed_hasher(keys),
+        peers: writers,
+        control,
+        open: BinaryHeap::new(),
+        open_states: StateMap::new(),
+        closed_states: StateMap::new(),
+        incumbent: i32::MAX,
+        sent: 0,
+        recv: 0,
+        log: Log::new(),
+    };
+    let mut node = Node::new(start.clone());
+    node.calc_cost(&worker.goal, worker.h_func);
+    node.hash = worker.hasher.hash(&start);
+    if worker.owner(node.hash) == rank {
+        worker.add(node);
+    }
+    let res = worker.run(&rx);
+    for stream in worker.peers.iter().flatten() {
+        stream.get_ref().shutdown();
+    }
+    res
+}
+
+struct Worker<'a, H: ?Sized> {
+    goal: Grid,
+    h_func: &'a H,
+    hasher: AnyHasher<Grid>,
+    // None at the rank of the worker
+    peers: Vec<Option<BufWriter<Stream>>>,
+    control: Stream,
+    open: BinaryHeap<Node>,
+    // g of the open and closed states
+    open_states: StateMap<Grid, i32>,
+    closed_states: StateMap<Grid, i32>,
+    incumbent: i32,
+    // nodes sent to and received from peers
+    sent: u64,
+    recv: u64,
+    log: Log,
+}
+
+impl<H: Heuristic<Grid> + ?Sized> Worker<'_, H> {
+    fn owner(&self, hash: u64) -> usize {
+        (hash % self.peers.len() as u64) as usize
+    }
+
+    fn run(&mut self, rx: &Receiver<((), Result<Msg>)>) -> Result<()> {
+        let mut since_flush = 0;
+        loop {
+            let msg = if self.busy() {
+                rx.try_recv().ok()
+            } else {
+                self.flush()?;
+                Some(
+                    rx.recv()
+                        .map_err(|_| Error::Protocol("connections closed".to_string()))?,
+                )
+            };
+            match msg {
+                Some((_, msg)) => {
+                    if self.handle(msg?)? {
+                        return Ok(());
+                    }
+                }
+                None => {
+                    self.expand()?;
+                    since_flush += 1;
+                    if since_flush == FLUSH_INTERVAL {
+                        self.flush()?;
+                        since_flush = 0;
+                    }
+                }
+            }
+        }
+    }
+
+    // true once stopped
+    fn handle(&mut self, msg: Msg) -> Result<bool> {
+        match msg {
+            Msg::Node(node) => {
+                self.recv += 1;
+                self.add(node);
+            }
+            Msg::Incumbent(cost) => self.incumbent = self.incumbent.min(cost),
+            Msg::Probe(round) => {
+                self.flush()?;
+                let report = Msg::Report {
+                    round,
+                    idle: !self.busy(),
+                    sent: self.sent,
+                    recv: self.recv,
+                };
+                report.write(&mut self.control)?;
+            }
+            Msg::Stop => {
+                self.flush()?;
+                Msg::Done(self.log.clone()).write(&mut self.control)?;
+                return Ok(true);
+            }
+            msg => return Err(unexpected(&msg)),
+        }
+        Ok(false)
+    }
+
+    // Whether the best open node is below the incumbent,
+    // drops nodes that were reached again cheaper.
+    fn busy(&mut self) -> bool {
+        while let Some(node) = self.open.peek() {
+            if self.open_states.get(node.hash, &node.state) == Some(&node.g) {
+                return node.f < self.incumbent;
+            }
+            self.open.pop();
+        }
+        false
+    }
+
+    fn add(&mut self, node: Node) {
+        self.log.iter_cnt += 1;
+        let seen = |g: Option<&i32>| g.is_some_and(|&g| g <= node.g);
+        if seen(self.closed_states.get(node.hash, &node.state))
+            || seen(self.open_states.get(node.hash, &node.state))
+        {
+            self.log.abort_cnt += 1;
+            return;
+        }
+        self.closed_states.remove(node.hash, &node.state);
+        self.open_states
+            .insert(node.hash, node.state.clone(), node.g);
+        self.open.push(node);
+    }
+
+    // Expands the best open node, called only when busy.
+    fn expand(&mut self) -> Result<()> {
+        let node = match self.open.pop() {
+            Some(node) => node,
+            None => return Ok(()),
+        };
+        self.open_states.remove(node.hash, &node.state);
+        if node.state == self.goal {
+            self.incumbent = node.g;
+            for peer in self.peers.iter_mut().flatten() {
+                Msg::Incumbent(node.g).write(peer)?;
+            }
+            Msg::Incumbent(node.g).write(&mut self.control)?;
+            return Ok(());
+        }
+        self.closed_states
+            .insert(node.hash, node.state.clone(), node.g);
+        self.log.exp_cnt += 1;
+
+        let succs = expand_hashed(&node, &self.goal, self.h_func, &self.hasher);
+        self.log.gen_cnt += succs.len() as i32;
+        for succ in succs {
+            if succ.f >= self.incumbent {
+                continue;
+            }
+            let owner = self.owner(succ.hash);
+            match self.peers[owner].as_mut() {
+                None => self.add(succ),
+                Some(peer) => {
+                    Msg::Node(succ).write(peer)?;
+                    self.sent += 1;
+                    self.log.node_cnt += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        for peer in self.peers.iter_mut().flatten() {
+            peer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::solver::tests::instances;
+    use crate::utils::helpers::weighted_man_dist;
+    use std::os::unix::net::UnixStream;
+    use std::thread::JoinHandle;
+
+    fn pair() -> (Stream, Stream) {
+        let (a, b) = UnixStream::pair().unwrap();
+        (Stream::Unix(a), Stream::Unix(b))
+    }
+
+    // Messages do not compare, their Debug forms do.
+    fn round_trip(msg: Msg, like: Option<&Grid>) {
+        let (mut a, mut b) = pair();
+        msg.write(&mut a).unwrap();
+        let read = Msg::read(&mut b, like).unwrap().unwrap();
+        assert_eq!(format!("{:?}", read), format!("{:?}", msg));
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let goal = Grid::rand_rect_with_seed(3, 4, 0);
+        let start = goal.rand_actions_with_seed(30, 1);
+        let mut node = Node::new(start.clone());
+        node.calc_cost(&goal, &weighted_man_dist);
+        node.hash = 0x0123_4567_89ab_cdef;
+        let msgs = vec![
+            Msg::Hello("tcp:127.0.0.1:4000".parse().unwrap()),
+            Msg::Setup {
+                rank: 1,
+                peers: vec![
+                    "unix:/tmp/a".parse().unwrap(),
+                    "unix:/tmp/b".parse().unwrap(),
+                ],
+                start,
+                goal: goal.clone(),
+                keys: Keys {
+                    width: KeyWidth::Bits128,
+                    seed: Some(5),
+                },
+            },
+            Msg::Peer(3),
+            Msg::Ready,
+            Msg::Start,
+            Msg::Node(node),
+            Msg::Incumbent(42),
+            Msg::Probe(7),
+            Msg::Report {
+                round: 7,
+                idle: true,
+                sent: 10,
+                recv: 9,
+            },
+            Msg::Stop,
+            Msg::Done(Log {
+                iter_cnt: 1,
+                node_cnt: 2,
+                abort_cnt: 3,
+                exp_cnt: 4,
+                gen_cnt: 5,
+            }),
+        ];
+        for msg in msgs {
+            round_trip(msg, Some(&goal));
+        }
+    }
+
+    #[test]
+    fn malformed_messages() {
+        let goal = Grid::rand_rect_with_seed(3, 3, 0);
+        let read = |tag: u8, body: &[u8], like: Option<&Grid>| {
+            let (mut a, mut b) = pair();
+            write_frame(&mut a, tag, body).unwrap();
+            Msg::read(&mut b, like)
+        };
+        assert!(matches!(read(200, &[], None), Err(Error::Protocol(_))));
+        assert!(matches!(read(PEER, &[0, 1], None), Err(Error::Protocol(_))));
+        assert!(matches!(
+            read(NODE, &[0; 16], None),
+            Err(Error::Protocol(_))
+        ));
+        assert!(matches!(
+            read(NODE, &[0; 16], Some(&goal)),
+            Err(Error::Protocol(_))
+        ));
+        let mut setup = vec![];
+        Msg::Setup {
+            rank: 0,
+            peers: vec![],
+            start: goal.clone(),
+            goal: goal.clone(),
+            keys: Keys::default(),
+        }
+        .write(&mut setup)
+        .unwrap();
+        // 256 bit keys
+        let mut body = setup[5..].to_vec();
+        body[4..8].copy_from_slice(&256u32.to_be_bytes());
+        assert!(matches!(read(SETUP, &body, None), Err(Error::Protocol(_))));
+        // the goal cut short
+        let body = &setup[5..setup.len() - 1];
+        assert!(matches!(read(SETUP, body, None), Err(Error::Protocol(_))));
+    }
+
+    #[test]
+    fn closed_stream() {
+        let (a, mut b) = pair();
+        drop(a);
+        assert!(Msg::read(&mut b, None).unwrap().is_none());
+        assert!(expect(&mut b, None).is_err());
+    }
+
+    // A worker on a thread of the test.
+    struct Thread(Option<JoinHandle<Result<()>>>);
+
+    impl Spawned for Thread {
+        fn check(&mut self) -> Result<()> {
+            match self.0.as_ref() {
+                Some(handle) if handle.is_finished() => {
+                    Err(Error::Protocol("worker exited".to_string()))
+                }
+                _ => Ok(()),
+            }
+        }
+
+        fn wait(&mut self) -> Result<()> {
+            match self.0.take() {
+                Some(handle) => handle.join().unwrap(),
+                None => Ok(()),
+            }
+        }
+
+        // A thread cannot be stopped, it fails once the
+        // coordinator closes its connection.
+        fn kill(&mut self) {}
+    }
+
+    #[test]
+    fn costs_match_seq() {
+        let socket = |name: String| {
+            let name = format!("astar-test-{}-{}.sock", std::process::id(), name);
+            Addr::Unix(std::env::temp_dir().join(name))
+        };
+        let addr = socket("coordinator".to_string());
+        let mut spawned = 0;
+        let mut spawn = |addr: &Addr| {
+            spawned += 1;
+            let (addr, listen) = (addr.clone(), socket(spawned.to_string()));
+            Ok(Thread(Some(thread::spawn(move || {
+                work(&addr, &listen, |_| Ok(Box::new(weighted_man_dist)))
+            }))))
+        };
+        let keys = Keys {
+            width: KeyWidth::Bits64,
+            seed: Some(3),
+        };
+        for (start, end, best) in instances(3, 3, 40) {
+            let limit = Limit::none();
+            let (node, logs) =
+                coordinate(&start, &end, 3, &addr, &mut spawn, keys, &limit).unwrap();
+            assert_eq!(node.unwrap().g, best, "{}", start);
+            assert_eq!(logs.len(), 3);
+        }
+        // the listeners removed their socket files
+        assert!(!matches!(&addr, Addr::Unix(path) if path.exists()));
+    }
+}
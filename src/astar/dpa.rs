@@ -5,21 +5,100 @@ use std::collections::{BinaryHeap, HashMap};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 use std::thread;
+use std::time::Instant;
 
-use crate::utils::helpers::expand;
-use crate::utils::structs::{Grid, Log, Node};
+use crate::astar::lockstep::{self, Router};
+use crate::utils::cost::Cost;
+use crate::utils::helpers::{derive_seed, expand};
+use crate::utils::heuristic::Heuristic;
+use crate::utils::structs::{Limit, Log, Node, State};
+use crate::utils::trace::{EventKind, Trace, Tracer};
 
-pub fn astar(
-    init_state: &Grid,
-    end_state: &Grid,
-    h_func: fn(&Grid, &Grid) -> i32,
+// Master seed of the free-running search.
+const SEED: u64 = 10;
+// Expansions per thread between two synchronizations in lock-step mode.
+pub const ROUND_LEN: usize = 16;
+
+pub fn astar<S: State, H: Heuristic<S> + 'static>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
+    num_threads: usize,
+    temp: f32,
+) -> Option<Node<S>> {
+    solve(
+        init_state,
+        end_state,
+        Arc::new(h_func),
+        num_threads,
+        temp,
+        Arc::new(Limit::none()),
+        false,
+    )
+    .0
+}
+
+// Same as astar, but also returns the log of every thread.
+pub fn astar_with_logs<S: State, H: Heuristic<S> + 'static>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
+    num_threads: usize,
+    temp: f32,
+) -> (Option<Node<S>>, Vec<Log>) {
+    let (end, logs, _) = solve(
+        init_state,
+        end_state,
+        Arc::new(h_func),
+        num_threads,
+        temp,
+        Arc::new(Limit::none()),
+        false,
+    );
+    (end, logs)
+}
+
+// Same as astar, but records the events of every thread.
+pub fn astar_traced<S: State, H: Heuristic<S> + 'static>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
+    num_threads: usize,
+    temp: f32,
+) -> (Option<Node<S>>, Trace) {
+    let (end, _, trace) = solve(
+        init_state,
+        end_state,
+        Arc::new(h_func),
+        num_threads,
+        temp,
+        Arc::new(Limit::none()),
+        true,
+    );
+    (end, trace)
+}
+
+// The search behind all variants above.
+// Threads stop once the limit is reached, None is returned
+// if no solution was found by then.
+pub fn solve<S: State, H: Heuristic<S> + ?Sized + 'static>(
+    init_state: &S,
+    end_state: &S,
+    h_func: Arc<H>,
     num_threads: usize,
     temp: f32,
-) -> Option<Node> {
+    limit: Arc<Limit>,
+    tracing: bool,
+) -> (Option<Node<S>>, Vec<Log>, Trace) {
     // let num_threads = 8;
     // Initialize termination variables
-    let msg_sent = Arc::new(AtomicU64::new(0));
-    let msg_recv = Arc::new(AtomicU64::new(0));
+    // pending counts the busy threads plus the nodes on their way, the
+    // search is over once it drops to zero. A busy thread holds one
+    // unit and adds one per node it sends, a thread going idle gives
+    // its unit up and an idle thread receiving a node takes over the
+    // unit of the node. So nothing is left to expand below the
+    // incumbent at zero, and the count never rises again.
+    let pending = Arc::new(AtomicU64::new(num_threads as u64));
     let term = Arc::new(AtomicBool::new(false));
 
     // Initialize communication channels
@@ -35,42 +114,54 @@ pub fn astar(
     let incumbent = Arc::new(RwLock::new(Node::new(init_state.clone())));
     {
         let mut inc = incumbent.write().unwrap();
-        inc.f = i32::MAX;
+        inc.f = S::Cost::MAX;
     }
 
     // Initialize threads
+    let start = Instant::now();
     let mut handles = Vec::with_capacity(num_threads);
-    for _ in 0..num_threads {
+    for i in 0..num_threads {
         let init_state = init_state.clone();
         let end_state = end_state.clone();
         let senders = senders.clone();
         let rx = receivers.remove(0);
-        let msg_sent = msg_sent.clone();
-        let msg_recv = msg_recv.clone();
+        let pending = pending.clone();
         let term = term.clone();
         let incumbent = incumbent.clone();
+        let limit = limit.clone();
+        let h_func = h_func.clone();
+        let mut tracer = if tracing {
+            Tracer::new(i, start)
+        } else {
+            Tracer::off()
+        };
         let h = thread::spawn(move || {
-            search(
+            let log = search(
                 &init_state,
                 &end_state,
                 incumbent,
                 num_threads as i32,
-                h_func,
+                &*h_func,
                 rx,
                 senders,
-                msg_sent,
-                msg_recv,
+                pending,
                 term,
                 temp,
-            )
+                derive_seed(SEED, i as u64),
+                limit,
+                &mut tracer,
+            );
+            (log, tracer.finish())
         });
         handles.push(h);
     }
 
-    let mut main_log = Log::new();
+    let mut logs = Vec::with_capacity(num_threads);
+    let mut trace = Trace::new();
     for h in handles {
-        let log = h.join().unwrap();
-        main_log.merge(log);
+        let (log, t) = h.join().unwrap();
+        logs.push(log);
+        trace.merge(t);
     }
     // println!(
     //     "average iteration: {}",
@@ -82,29 +173,96 @@ pub fn astar(
     // println!("terminated!");
     // with RwLock
     let end = incumbent.read().unwrap().clone();
-    Some(end)
+    if end.f == S::Cost::MAX {
+        return (None, logs, trace);
+    }
+    (Some(end), logs, trace)
+}
+
+// Sends a successor to a random thread with probability temp,
+// keeps it otherwise.
+pub struct RandRouter {
+    rng: rand::rngs::StdRng,
+    temp: f32,
+    thread_num: usize,
+    num_threads: usize,
+}
+
+impl RandRouter {
+    pub fn new(seed: u64, temp: f32, thread_num: usize, num_threads: usize) -> Self {
+        RandRouter {
+            rng: rand::rngs::StdRng::seed_from_u64(seed),
+            temp,
+            thread_num,
+            num_threads,
+        }
+    }
+}
+
+impl<S: State> Router<S> for RandRouter {
+    fn route(&mut self, _: &Node<S>) -> usize {
+        let t: f32 = self.rng.gen();
+        if t < self.temp {
+            self.rng.gen_range(0..self.num_threads)
+        } else {
+            self.thread_num
+        }
+    }
+}
+
+// Deterministic variant of astar: threads run in lock-step rounds and
+// draw from seeds derived from the master seed, so the expansion order
+// and the returned log are the same on every run.
+pub fn astar_lockstep<S: State, H: Heuristic<S> + 'static>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
+    num_threads: usize,
+    temp: f32,
+    seed: u64,
+) -> (Option<Node<S>>, Log) {
+    let routers = (0..num_threads)
+        .map(|i| RandRouter::new(derive_seed(seed, i as u64), temp, i, num_threads))
+        .collect();
+    let (end, logs) = lockstep::astar(
+        init_state,
+        end_state,
+        Arc::new(h_func),
+        routers,
+        ROUND_LEN,
+        Arc::new(Limit::none()),
+    );
+    let mut main_log = Log::new();
+    for log in logs {
+        main_log.merge(log);
+    }
+    (end, main_log)
 }
 
-pub fn search(
-    start_state: &Grid,
-    end_state: &Grid,
-    incumbent: Arc<RwLock<Node>>,
+#[allow(clippy::too_many_arguments)]
+pub fn search<S: State, H: Heuristic<S> + ?Sized>(
+    start_state: &S,
+    end_state: &S,
+    incumbent: Arc<RwLock<Node<S>>>,
     num_threads: i32,
-    h_func: fn(&Grid, &Grid) -> i32,
-    rx: Receiver<Node>,
-    senders: Vec<Sender<Node>>,
-    msg_sent: Arc<AtomicU64>,
-    msg_recv: Arc<AtomicU64>,
+    h_func: &H,
+    rx: Receiver<Node<S>>,
+    senders: Vec<Sender<Node<S>>>,
+    pending: Arc<AtomicU64>,
     term: Arc<AtomicBool>,
     temp: f32,
+    seed: u64,
+    limit: Arc<Limit>,
+    tracer: &mut Tracer,
 ) -> Log {
     // let mut first_iteration = true;
-    let mut buffer: BinaryHeap<Node> = BinaryHeap::new();
-    let mut queue: BinaryHeap<Node> = BinaryHeap::new();
-    let mut open_states: HashMap<Grid, i32> = HashMap::new(); // map grid -> f
-    let mut closed_states: HashMap<Grid, i32> = HashMap::new(); // map grid -> f
-    let mut rng = rand::rngs::StdRng::seed_from_u64(10);
+    let mut buffer: BinaryHeap<Node<S>> = BinaryHeap::new();
+    let mut queue: BinaryHeap<Node<S>> = BinaryHeap::new();
+    let mut open_states: HashMap<S, S::Cost> = HashMap::new(); // map grid -> f
+    let mut closed_states: HashMap<S, S::Cost> = HashMap::new(); // map grid -> f
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
     let mut log = Log::new();
+    let mut idle = false;
 
     // Initialization
     let mut start = Node::new(start_state.clone());
@@ -116,8 +274,6 @@ pub fn search(
         log.iter_cnt += 1;
         // Termination detection
         if term.load(Ordering::SeqCst) {
-            // println!("sent: {}", msg_sent.load(Ordering::SeqCst));
-            // println!("received: {}", msg_recv.load(Ordering::SeqCst));
             // println!("#iter: {}", log.iter_cnt);
             break;
         }
@@ -129,7 +285,12 @@ pub fn search(
         if log.iter_cnt % freq == 0 {
             loop {
                 if let Ok(msg) = rx.try_recv() {
-                    // msg_recv.fetch_add(1, Ordering::SeqCst);
+                    if idle {
+                        idle = false;
+                    } else {
+                        pending.fetch_sub(1, Ordering::SeqCst);
+                    }
+                    tracer.instant(EventKind::Recv, msg.f.to_f64());
                     buffer.push(msg);
                     continue;
                 }
@@ -143,7 +304,7 @@ pub fn search(
 
             // if incoming node is in closed states
             if let Some(&g) = closed_states.get(&node.state) {
-                if g > node.g {
+                if node.g.improves(g) {
                     // reopen state if it has lower cost.
                     closed_states.remove(&node.state);
                 } else {
@@ -154,7 +315,7 @@ pub fn search(
             // If incoming node is in open states
             if open_states.contains_key(&node.state) {
                 // skip if node has higher cost
-                if node.f >= *open_states.get(&node.state).unwrap() {
+                if !node.f.improves(*open_states.get(&node.state).unwrap()) {
                     continue;
                 }
             }
@@ -170,10 +331,28 @@ pub fn search(
 
         // Expand node from local queue
         // skip if open_states is empty or local node is worse than incumbent
-        if open_states.is_empty() || queue.peek().unwrap().f >= incumbent.read().unwrap().f {
+        if open_states.is_empty()
+            || !queue
+                .peek()
+                .unwrap()
+                .f
+                .improves(incumbent.read().unwrap().f)
+        {
             log.abort_cnt += 1;
+            if limit.reached() {
+                term.store(true, Ordering::SeqCst);
+            }
+            if !idle {
+                idle = true;
+                pending.fetch_sub(1, Ordering::SeqCst);
+            }
+            if pending.load(Ordering::SeqCst) == 0 {
+                break;
+            }
+            tracer.idle();
             continue;
         }
+        tracer.busy();
 
         let mut node;
         // nodes in local queue may no longer exist in open states
@@ -187,27 +366,37 @@ pub fn search(
         open_states.remove(&node.state);
         closed_states.insert(node.state.clone(), node.g);
 
+        // A goal only bounds the search, cheaper ones may still be
+        // open elsewhere.
         if node.state == *end_state {
-            // println!("Reach end state.");
-            term.store(true, Ordering::SeqCst);
             let mut incumbent = incumbent.write().unwrap();
-            if node.f < incumbent.f {
+            if node.f.improves(incumbent.f) {
                 *incumbent = node.clone();
-                continue;
+                tracer.instant(EventKind::Incumbent, node.f.to_f64());
             }
+            continue;
         }
 
+        let expand_start = tracer.now();
         let successors = expand(&node, end_state, h_func);
+        log.exp_cnt += 1;
+        log.gen_cnt += successors.len() as i32;
+        if limit.tick() {
+            term.store(true, Ordering::SeqCst);
+        }
         for succ in successors {
             let t: f32 = rng.gen();
             // temp regulates frequency of communication
             if t < temp {
+                // Counted before it leaves, the receiver may be done with it
+                // before send returns.
+                pending.fetch_add(1, Ordering::SeqCst);
                 loop {
                     let i = rng.gen_range(0..num_threads);
                     match senders[i as usize].send(succ.clone()) {
                         Ok(_) => {
                             log.node_cnt += 1;
-                            // msg_sent.fetch_add(1, Ordering::SeqCst);
+                            tracer.instant(EventKind::Send, i as f64);
                             break;
                         }
                         Err(_) => continue,
@@ -217,6 +406,7 @@ pub fn search(
                 buffer.push(succ);
             }
         }
+        tracer.span(EventKind::Expand, expand_start, node.f.to_f64());
     }
     log
 }
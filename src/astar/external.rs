@@ -0,0 +1,409 @@
+// External memory A* (Edelkamp, Jabbar and Schroedl) for searches whose
+// open and closed lists do not fit in RAM.
+// States are kept on disk, packed, in one bucket per pair of g and h.
+// Buckets are expanded in order of f, then g. Duplicates are removed late,
+// when a bucket is expanded: its new states are sorted in runs of bounded
+// size, the runs are merged, and the merge is subtracted from the sorted
+// runs of the buckets expanded before with the same h and a g that is not
+// larger. A state always has the same h, so no earlier copy can be anywhere
+// else.
+// Everything is streamed from disk: only one run being sorted, or one slice
+// of states per thread being expanded, is held in memory.
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::fs::{create_dir_all, remove_dir_all, remove_file, File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::error::Result;
+use crate::utils::cost::Cost;
+use crate::utils::heuristic::Heuristic;
+use crate::utils::structs::{Limit, Log, Node, State};
+
+// States one thread expands between writes of the successors.
+const SLICE: usize = 1 << 16;
+// States sorted in memory at a time when a bucket is expanded.
+const RUN: usize = 1 << 20;
+
+// Packed successors by their g and h.
+type Succs<C> = BTreeMap<(C, C), Vec<u8>>;
+
+static SEARCHES: AtomicUsize = AtomicUsize::new(0);
+
+// Files of the search, removed with it.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(parent: &Path) -> Result<TempDir> {
+        let id = SEARCHES.fetch_add(1, Ordering::Relaxed);
+        let path = parent.join(format!("astar-external-{}-{}", std::process::id(), id));
+        create_dir_all(&path)?;
+        Ok(TempDir(path))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = remove_dir_all(&self.0);
+    }
+}
+
+#[derive(Default)]
+struct Bucket {
+    // successors not expanded yet, in no order
+    pending: Option<PathBuf>,
+    // sorted states expanded from this bucket, one file per expansion
+    runs: Vec<PathBuf>,
+}
+
+struct Buckets<C> {
+    dir: TempDir,
+    files: usize,
+    // by (f, g, h)
+    map: BTreeMap<(C, C, C), Bucket>,
+}
+
+impl<C: Ord + Copy> Buckets<C> {
+    fn file(&mut self) -> PathBuf {
+        self.files += 1;
+        self.dir.0.join(format!("{}.bin", self.files))
+    }
+
+    fn append(&mut self, key: (C, C, C), states: &[u8]) -> Result<()> {
+        let path = match self.map.get(&key).and_then(|b| b.pending.clone()) {
+            Some(path) => path,
+            None => {
+                let path = self.file();
+                self.map.entry(key).or_default().pending = Some(path.clone());
+                path
+            }
+        };
+        let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+        f.write_all(states)?;
+        Ok(())
+    }
+}
+
+pub fn astar<S: State, H: Heuristic<S>>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
+    num_threads: usize,
+) -> Result<Option<Node<S>>> {
+    let limit = Limit::none();
+    let dir = std::env::temp_dir();
+    Ok(solve(init_state, end_state, &h_func, num_threads, &dir, &limit)?.0)
+}
+
+// The files go to a new directory in dir.
+pub fn solve<S: State, H: Heuristic<S> + ?Sized>(
+    init_state: &S,
+    end_state: &S,
+    h_func: &H,
+    num_threads: usize,
+    dir: &Path,
+    limit: &Limit,
+) -> Result<(Option<Node<S>>, Log)> {
+    search(init_state, end_state, h_func, num_threads, dir, limit, RUN)
+}
+
+fn search<S: State, H: Heuristic<S> + ?Sized>(
+    init_state: &S,
+    end_state: &S,
+    h_func: &H,
+    num_threads: usize,
+    dir: &Path,
+    limit: &Limit,
+    run_len: usize,
+) -> Result<(Option<Node<S>>, Log)> {
+    let mut log = Log::new();
+    let mut buckets = Buckets {
+        dir: TempDir::new(dir)?,
+        files: 0,
+        map: BTreeMap::new(),
+    };
+    let mut goal = vec![];
+    end_state.pack(&mut goal);
+    let width = goal.len();
+    let mut start = vec![];
+    init_state.pack(&mut start);
+    let h = h_func.h(init_state, end_state);
+    buckets.append((h, S::Cost::ZERO, h), &start)?;
+
+    while let Some(key) = buckets
+        .map
+        .iter()
+        .find(|(_, b)| b.pending.is_some())
+        .map(|(key, _)| *key)
+    {
+        let (f, g, h) = key;
+        let path = buckets.map.get_mut(&key).unwrap().pending.take().unwrap();
+        let sorted = sort_runs(&mut buckets, &path, width, run_len)?;
+        remove_file(&path)?;
+        let earlier: Vec<PathBuf> = buckets
+            .map
+            .iter()
+            .filter(|(k, _)| k.2 == h && k.1 <= g)
+            .flat_map(|(_, b)| b.runs.iter().cloned())
+            .collect();
+
+        // Merge the new runs, drop the duplicates and the states of the
+        // earlier runs, and write what is left as the run of this expansion.
+        let mut new = Merge::open(&sorted, width)?;
+        let mut old = Merge::open(&earlier, width)?;
+        let mut o = vec![0; width];
+        let mut o_more = old.next(&mut o)?;
+        let mut s = vec![0; width];
+        let mut prev: Option<Vec<u8>> = None;
+        let (mut count, mut found) = (0, false);
+        let run = buckets.file();
+        let mut w = BufWriter::new(File::create(&run)?);
+        while new.next(&mut s)? {
+            if prev.as_ref() == Some(&s) {
+                continue;
+            }
+            prev = Some(s.clone());
+            while o_more && o < s {
+                o_more = old.next(&mut o)?;
+            }
+            if o_more && o == s {
+                continue;
+            }
+            found |= s == goal;
+            w.write_all(&s)?;
+            count += 1;
+        }
+        w.flush()?;
+        drop((w, new));
+        for path in sorted.iter() {
+            remove_file(path)?;
+        }
+        if count == 0 {
+            remove_file(&run)?;
+            continue;
+        }
+        log.iter_cnt += 1;
+        if found {
+            let mut node = Node::new(end_state.clone());
+            (node.f, node.g, node.h) = (f, g, h);
+            return Ok((Some(node), log));
+        }
+        buckets.map.get_mut(&key).unwrap().runs.push(run.clone());
+
+        let mut r = BufReader::new(File::open(&run)?);
+        let mut bytes = vec![];
+        loop {
+            bytes.clear();
+            let want = (SLICE * num_threads.max(1) * width) as u64;
+            (&mut r).take(want).read_to_end(&mut bytes)?;
+            if bytes.is_empty() {
+                break;
+            }
+            let slice: Vec<&[u8]> = bytes.chunks_exact(width).collect();
+            let found = thread::scope(|sc| {
+                let handles: Vec<_> = slice
+                    .chunks(SLICE)
+                    .map(|part| {
+                        sc.spawn(move || expand_part(part, init_state, g, end_state, h_func, limit))
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|h| h.join().unwrap())
+                    .collect::<Vec<_>>()
+            });
+            for (expanded, succs) in found {
+                log.exp_cnt += expanded as i32;
+                for ((g, h), packed) in succs {
+                    log.gen_cnt += (packed.len() / width) as i32;
+                    buckets.append((g + h, g, h), &packed)?;
+                }
+            }
+            if limit.reached() {
+                return Ok((None, log));
+            }
+        }
+    }
+    Ok((None, log))
+}
+
+// Successors of the packed states of a bucket at cost g, packed
+// and grouped by their g and h, with the number of states expanded
+// before the limit was reached.
+fn expand_part<S: State, H: Heuristic<S> + ?Sized>(
+    part: &[&[u8]],
+    like: &S,
+    g: S::Cost,
+    end_state: &S,
+    h_func: &H,
+    limit: &Limit,
+) -> (usize, Succs<S::Cost>) {
+    let mut succs: Succs<S::Cost> = BTreeMap::new();
+    let mut expanded = 0;
+    for packed in part {
+        if limit.tick() {
+            break;
+        }
+        expanded += 1;
+        let state = S::unpack(packed, like);
+        for (t, c) in state.successors() {
+            let h = h_func.h(&t, end_state);
+            t.pack(succs.entry((g + c, h)).or_default());
+        }
+    }
+    (expanded, succs)
+}
+
+// Cuts the pending file into runs of at most run_len states, each sorted
+// and deduplicated in memory and written to a file of its own.
+fn sort_runs<C: Ord + Copy>(
+    buckets: &mut Buckets<C>,
+    pending: &Path,
+    width: usize,
+    run_len: usize,
+) -> Result<Vec<PathBuf>> {
+    let mut r = BufReader::new(File::open(pending)?);
+    let mut bytes = vec![];
+    let mut runs = vec![];
+    loop {
+        bytes.clear();
+        (&mut r)
+            .take((run_len * width) as u64)
+            .read_to_end(&mut bytes)?;
+        if bytes.is_empty() {
+            return Ok(runs);
+        }
+        let mut states: Vec<&[u8]> = bytes.chunks_exact(width).collect();
+        states.sort_unstable();
+        states.dedup();
+        let run = buckets.file();
+        let mut w = BufWriter::new(File::create(&run)?);
+        for s in states {
+            w.write_all(s)?;
+        }
+        w.flush()?;
+        runs.push(run);
+    }
+}
+
+// K-way merge of sorted runs, one record of each run in memory.
+struct Merge {
+    readers: Vec<BufReader<File>>,
+    heap: BinaryHeap<Reverse<(Vec<u8>, usize)>>,
+}
+
+impl Merge {
+    fn open(runs: &[PathBuf], width: usize) -> Result<Merge> {
+        let mut merge = Merge {
+            readers: Vec::with_capacity(runs.len()),
+            heap: BinaryHeap::with_capacity(runs.len()),
+        };
+        for (i, run) in runs.iter().enumerate() {
+            let mut r = BufReader::new(File::open(run)?);
+            let mut rec = vec![0; width];
+            if next_record(&mut r, &mut rec)? {
+                merge.heap.push(Reverse((rec, i)));
+            }
+            merge.readers.push(r);
+        }
+        Ok(merge)
+    }
+
+    // Moves the smallest record left into rec, which must have the
+    // width of a record. False once every run is done.
+    fn next(&mut self, rec: &mut Vec<u8>) -> Result<bool> {
+        let Some(Reverse((mut top, i))) = self.heap.pop() else {
+            return Ok(false);
+        };
+        std::mem::swap(rec, &mut top);
+        if next_record(&mut self.readers[i], &mut top)? {
+            self.heap.push(Reverse((top, i)));
+        }
+        Ok(true)
+    }
+}
+
+fn next_record<R: Read>(r: &mut R, rec: &mut [u8]) -> Result<bool> {
+    match r.read_exact(rec) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::astar::seq;
+    use crate::solver::tests::instances;
+    use crate::utils::helpers::weighted_man_dist;
+    use crate::utils::structs::{Grid, TileCost};
+
+    #[test]
+    fn costs_match_seq() {
+        let dir = std::env::temp_dir().join(format!("astar-external-test-{}", std::process::id()));
+        for (start, end, best) in instances(4, 4, 40) {
+            for threads in [1, 3] {
+                let limit = Limit::none();
+                let (node, _) =
+                    solve(&start, &end, &weighted_man_dist, threads, &dir, &limit).unwrap();
+                assert_eq!(node.unwrap().g, best, "{}", start);
+            }
+        }
+        // Every search removes its files.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn many_runs_per_bucket() {
+        let dir = std::env::temp_dir().join(format!("astar-external-runs-{}", std::process::id()));
+        let end = Grid::rand_with_seed(3, 7).with_cost(TileCost::Heavy);
+        for seed in 0..4 {
+            let start = end.rand_actions_with_seed(40, seed);
+            let best = seq::astar(&start, &end, weighted_man_dist).unwrap().g;
+            for run_len in [1, 3] {
+                let limit = Limit::none();
+                let (node, _) =
+                    search(&start, &end, &weighted_man_dist, 2, &dir, &limit, run_len).unwrap();
+                assert_eq!(node.unwrap().g, best, "{}", start);
+            }
+        }
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn merge_of_runs() {
+        let dir = TempDir::new(&std::env::temp_dir()).unwrap();
+        let runs: Vec<PathBuf> = [&[1u8, 4, 7][..], &[2, 4], &[], &[0, 9]]
+            .iter()
+            .enumerate()
+            .map(|(i, run)| {
+                let path = dir.0.join(format!("{}", i));
+                std::fs::write(&path, run).unwrap();
+                path
+            })
+            .collect();
+        let mut merge = Merge::open(&runs, 1).unwrap();
+        let mut rec = vec![0];
+        let mut merged = vec![];
+        while merge.next(&mut rec).unwrap() {
+            merged.push(rec[0]);
+        }
+        assert_eq!(merged, [0, 1, 2, 4, 4, 7, 9]);
+    }
+
+    #[test]
+    fn expansions_stop_at_the_limit() {
+        let end = Grid::rand_with_seed(4, 69);
+        let start = end.rand_actions_with_seed(200, 0);
+        let limit = Limit::new(Some(100), None);
+        let dir = std::env::temp_dir();
+        let (node, log) = solve(&start, &end, &weighted_man_dist, 1, &dir, &limit).unwrap();
+        assert!(node.is_none());
+        // the tick that reaches the limit does not expand
+        assert_eq!(log.exp_cnt, 99);
+    }
+}
@@ -0,0 +1,244 @@
+// Frontier A* (Korf, Zhang, Thayer and Hohwald): A* without a closed list.
+// Every open state remembers which of its moves lead to states expanded
+// already, as bits of move ids (State::moves), and never makes them, so an
+// expanded state is not generated again and can be forgotten. That needs
+// a consistent heuristic and moves that can be undone at the same cost,
+// which both domains have.
+// Without a closed list there are no parents to follow back. solve_path
+// finds the cost c of an optimal path first, then searches again while
+// every open state keeps the move of its path where g passes c / 2, and
+// solves both halves the same way (divide and conquer).
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::utils::cost::Cost;
+use crate::utils::heuristic::Heuristic;
+use crate::utils::structs::{Limit, Log, Node, State};
+
+// The move of a path from a state with cost g to one with cost g + c
+// where 2 * g <= split < 2 * (g + c).
+struct Cross<S: State> {
+    from: S,
+    from_g: S::Cost,
+    to: S,
+    to_g: S::Cost,
+}
+
+impl<S: State> Clone for Cross<S> {
+    fn clone(&self) -> Self {
+        Cross {
+            from: self.from.clone(),
+            from_g: self.from_g,
+            to: self.to.clone(),
+            to_g: self.to_g,
+        }
+    }
+}
+
+struct Entry<S: State> {
+    g: S::Cost,
+    // bit i is set if move i leads to an expanded state
+    used: u64,
+    cross: Option<Cross<S>>,
+}
+
+pub fn astar<S: State, H: Heuristic<S>>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
+) -> Option<Node<S>> {
+    solve(init_state, end_state, &h_func, &Limit::none()).0
+}
+
+pub fn solve<S: State, H: Heuristic<S> + ?Sized>(
+    init_state: &S,
+    end_state: &S,
+    h_func: &H,
+    limit: &Limit,
+) -> (Option<Node<S>>, Log) {
+    let (node, _, log) = search(init_state, end_state, h_func, limit, None);
+    (node, log)
+}
+
+// The states of an optimal path from init_state to end_state.
+pub fn path<S: State, H: Heuristic<S>>(init_state: &S, end_state: &S, h_func: H) -> Option<Vec<S>> {
+    solve_path(init_state, end_state, &h_func, &Limit::none()).0
+}
+
+pub fn solve_path<S: State, H: Heuristic<S> + ?Sized>(
+    init_state: &S,
+    end_state: &S,
+    h_func: &H,
+    limit: &Limit,
+) -> (Option<Vec<S>>, Log) {
+    let (node, mut log) = solve(init_state, end_state, h_func, limit);
+    let mut path = vec![init_state.clone()];
+    let found = match node {
+        Some(node) => extend(
+            &mut path, init_state, end_state, node.g, h_func, limit, &mut log,
+        ),
+        None => false,
+    };
+    (found.then_some(path), log)
+}
+
+// Appends the states after from of an optimal path to to, which costs cost.
+fn extend<S: State, H: Heuristic<S> + ?Sized>(
+    path: &mut Vec<S>,
+    from: &S,
+    to: &S,
+    cost: S::Cost,
+    h_func: &H,
+    limit: &Limit,
+    log: &mut Log,
+) -> bool {
+    if from == to {
+        return true;
+    }
+    if from
+        .successors()
+        .iter()
+        .any(|(t, c)| t == to && !cost.improves(*c))
+    {
+        path.push(to.clone());
+        return true;
+    }
+    let (_, cross, l) = search(from, to, h_func, limit, Some(cost));
+    log.merge(l);
+    let cross = match cross {
+        Some(cross) => cross,
+        None => return false,
+    };
+    if !extend(path, from, &cross.from, cross.from_g, h_func, limit, log) {
+        return false;
+    }
+    path.push(cross.to.clone());
+    extend(path, &cross.to, to, cost - cross.to_g, h_func, limit, log)
+}
+
+// With split, also returns the move where the path to the goal
+// passes split / 2.
+fn search<S: State, H: Heuristic<S> + ?Sized>(
+    init_state: &S,
+    end_state: &S,
+    h_func: &H,
+    limit: &Limit,
+    split: Option<S::Cost>,
+) -> (Option<Node<S>>, Option<Cross<S>>, Log) {
+    let mut log = Log::new();
+    let mut start = Node::new(init_state.clone());
+    start.calc_cost(end_state, h_func);
+    let mut open: BinaryHeap<Node<S>> = BinaryHeap::new();
+    let mut frontier: HashMap<S, Entry<S>> = HashMap::new();
+    frontier.insert(
+        init_state.clone(),
+        Entry {
+            g: S::Cost::ZERO,
+            used: 0,
+            cross: None,
+        },
+    );
+    open.push(start);
+
+    while let Some(n) = open.pop() {
+        // Stale copies were improved or expanded since.
+        if frontier.get(&n.state).is_none_or(|e| e.g != n.g) {
+            continue;
+        }
+        log.iter_cnt += 1;
+        let entry = frontier.remove(&n.state).unwrap();
+        if n.state == *end_state {
+            return (Some(n), entry.cross, log);
+        }
+
+        log.exp_cnt += 1;
+        for (state, c, id, back) in n.state.moves() {
+            if entry.used & (1 << id) != 0 {
+                continue;
+            }
+            log.gen_cnt += 1;
+            let g = n.g + c;
+            let cross = match (&entry.cross, split) {
+                (Some(cross), _) => Some(cross.clone()),
+                (None, Some(split)) if n.g + n.g <= split && split < g + g => Some(Cross {
+                    from: n.state.clone(),
+                    from_g: n.g,
+                    to: state.clone(),
+                    to_g: g,
+                }),
+                _ => None,
+            };
+            let back = back.map_or(0, |b| 1 << b);
+            let improved = match frontier.get_mut(&state) {
+                Some(e) => {
+                    e.used |= back;
+                    if g.improves(e.g) {
+                        e.g = g;
+                        e.cross = cross;
+                        true
+                    } else {
+                        false
+                    }
+                }
+                None => {
+                    frontier.insert(
+                        state.clone(),
+                        Entry {
+                            g,
+                            used: back,
+                            cross,
+                        },
+                    );
+                    true
+                }
+            };
+            if improved {
+                let mut node = Node::new(state);
+                node.g = g;
+                node.calc_cost(end_state, h_func);
+                open.push(node);
+            }
+        }
+        if limit.tick() {
+            break;
+        }
+    }
+    (None, None, log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::tests::instances;
+    use crate::utils::helpers::weighted_man_dist;
+    use crate::utils::structs::Grid;
+
+    // Cost of a path made of moves, None if two states are not one move apart.
+    fn path_cost(path: &[Grid]) -> Option<i32> {
+        path.windows(2)
+            .map(|w| {
+                w[0].successors()
+                    .into_iter()
+                    .find(|(s, _)| *s == w[1])
+                    .map(|(_, c)| c)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn paths_are_optimal_moves() {
+        for (rows, cols) in [(3, 3), (2, 4), (4, 4)] {
+            for (start, end, best) in instances(rows, cols, 30) {
+                let path = path(&start, &end, weighted_man_dist).unwrap();
+                assert_eq!(path.first(), Some(&start));
+                assert_eq!(path.last(), Some(&end));
+                assert_eq!(path_cost(&path), Some(best), "{} to {}", start, end);
+            }
+        }
+    }
+
+    #[test]
+    fn path_to_itself() {
+        let end = Grid::rand_with_seed(3, 0);
+        assert_eq!(path(&end, &end, weighted_man_dist), Some(vec![end]));
+    }
+}
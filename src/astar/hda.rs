@@ -1,24 +1,177 @@
 #![allow(unused_variables)]
 use crossbeam::channel::{Receiver, Sender};
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::BinaryHeap;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 use std::thread;
+use std::time::Instant;
 
-use crate::utils::helpers::expand;
-use crate::utils::structs::{Grid, Log, Node, StateHash};
+use crate::astar::dpa::ROUND_LEN;
+use crate::astar::lockstep::{self, Router};
+use crate::utils::cost::Cost;
+use crate::utils::helpers::expand_hashed;
+use crate::utils::heuristic::Heuristic;
+use crate::utils::structs::{Limit, Log, Node, State, StateHasher};
+use crate::utils::table::StateMap;
+use crate::utils::trace::{EventKind, Trace, Tracer};
 
-pub fn astar<T: StateHash + Clone + Send + 'static>(
-    init_state: &Grid,
-    end_state: &Grid,
-    h_func: fn(&Grid, &Grid) -> i32,
+pub fn astar<S: State, T: StateHasher<S>, H: Heuristic<S> + 'static>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
     num_threads: usize,
     hasher: T,
-) -> Option<Node> {
+) -> Option<Node<S>> {
+    solve(
+        init_state,
+        end_state,
+        Arc::new(h_func),
+        num_threads,
+        hasher,
+        Arc::new(Limit::none()),
+        false,
+    )
+    .0
+}
+
+// ZHDA*: the tables are keyed by hasher, abstract_hasher only picks
+// the thread of a state.
+pub fn astar_abstract<S: State, T: StateHasher<S>, A: StateHasher<S>, H: Heuristic<S> + 'static>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
+    num_threads: usize,
+    hasher: T,
+    abstract_hasher: A,
+) -> Option<Node<S>> {
+    solve_abstract(
+        init_state,
+        end_state,
+        Arc::new(h_func),
+        num_threads,
+        hasher,
+        abstract_hasher,
+        Arc::new(Limit::none()),
+        false,
+    )
+    .0
+}
+
+// Same as astar, but also returns the log of every thread.
+pub fn astar_with_logs<S: State, T: StateHasher<S>, H: Heuristic<S> + 'static>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
+    num_threads: usize,
+    hasher: T,
+) -> (Option<Node<S>>, Vec<Log>) {
+    let (end, logs, _) = solve(
+        init_state,
+        end_state,
+        Arc::new(h_func),
+        num_threads,
+        hasher,
+        Arc::new(Limit::none()),
+        false,
+    );
+    (end, logs)
+}
+
+// Same as astar, but records the events of every thread.
+pub fn astar_traced<S: State, T: StateHasher<S>, H: Heuristic<S> + 'static>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
+    num_threads: usize,
+    hasher: T,
+) -> (Option<Node<S>>, Trace) {
+    let (end, _, trace) = solve(
+        init_state,
+        end_state,
+        Arc::new(h_func),
+        num_threads,
+        hasher,
+        Arc::new(Limit::none()),
+        true,
+    );
+    (end, trace)
+}
+
+// The search behind all variants above, a state goes to the thread
+// of its hash under hasher, which also keys the tables.
+// Threads stop once the limit is reached, None is returned
+// if no solution was found by then.
+pub fn solve<S: State, T: StateHasher<S>, H: Heuristic<S> + ?Sized + 'static>(
+    init_state: &S,
+    end_state: &S,
+    h_func: Arc<H>,
+    num_threads: usize,
+    hasher: T,
+    limit: Arc<Limit>,
+    tracing: bool,
+) -> (Option<Node<S>>, Vec<Log>, Trace) {
+    solve_routed(
+        init_state,
+        end_state,
+        h_func,
+        num_threads,
+        hasher,
+        ByHash,
+        limit,
+        tracing,
+    )
+}
+
+// solve with the tables keyed by hasher and states sent to the
+// thread of their hash under abstract_hasher, see astar_abstract.
+#[allow(clippy::too_many_arguments)]
+pub fn solve_abstract<
+    S: State,
+    T: StateHasher<S>,
+    A: StateHasher<S>,
+    H: Heuristic<S> + ?Sized + 'static,
+>(
+    init_state: &S,
+    end_state: &S,
+    h_func: Arc<H>,
+    num_threads: usize,
+    hasher: T,
+    abstract_hasher: A,
+    limit: Arc<Limit>,
+    tracing: bool,
+) -> (Option<Node<S>>, Vec<Log>, Trace) {
+    solve_routed(
+        init_state,
+        end_state,
+        h_func,
+        num_threads,
+        hasher,
+        ByAbstract(abstract_hasher),
+        limit,
+        tracing,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn solve_routed<S: State, T: StateHasher<S>, R: Route<S>, H: Heuristic<S> + ?Sized + 'static>(
+    init_state: &S,
+    end_state: &S,
+    h_func: Arc<H>,
+    num_threads: usize,
+    hasher: T,
+    route: R,
+    limit: Arc<Limit>,
+    tracing: bool,
+) -> (Option<Node<S>>, Vec<Log>, Trace) {
     // let num_threads = 8;
     // Initialize termination variables
-    let msg_sent = Arc::new(AtomicU64::new(0));
-    let msg_recv = Arc::new(AtomicU64::new(0));
+    // pending counts the busy threads plus the nodes on their way, the
+    // search is over once it drops to zero. A busy thread holds one
+    // unit and adds one per node it sends, a thread going idle gives
+    // its unit up and an idle thread receiving a node takes over the
+    // unit of the node. So nothing is left to expand below the
+    // incumbent at zero, and the count never rises again.
+    let pending = Arc::new(AtomicU64::new(num_threads as u64));
     let term = Arc::new(AtomicBool::new(false));
 
     // Initialize communication channels
@@ -34,46 +187,59 @@ pub fn astar<T: StateHash + Clone + Send + 'static>(
     let incumbent = Arc::new(RwLock::new(Node::new(init_state.clone())));
     {
         let mut inc = incumbent.write().unwrap();
-        inc.f = i32::MAX;
+        inc.f = S::Cost::MAX;
     }
 
     // Initialize threads
     // let hasher = ZHasher::new(init_state.size);
     // let hasher = AZHasher::new(init_state.size);
+    let start = Instant::now();
     let mut handles = Vec::with_capacity(num_threads);
     for i in 0..num_threads {
         let init_state = init_state.clone();
         let end_state = end_state.clone();
         let senders = senders.clone();
         let rx = receivers.remove(0);
-        let msg_sent = msg_sent.clone();
-        let msg_recv = msg_recv.clone();
+        let pending = pending.clone();
         let term = term.clone();
         let incumbent = incumbent.clone();
+        let limit = limit.clone();
+        let h_func = h_func.clone();
         let hasher = hasher.clone();
+        let route = route.clone();
+        let mut tracer = if tracing {
+            Tracer::new(i, start)
+        } else {
+            Tracer::off()
+        };
         let h = thread::spawn(move || {
-            search(
+            let log = search(
                 &init_state,
                 &end_state,
                 incumbent,
                 num_threads as i32,
-                h_func,
+                &*h_func,
                 i as i32,
                 rx,
                 senders,
-                msg_sent,
-                msg_recv,
+                pending,
                 term,
                 hasher,
-            )
+                route,
+                limit,
+                &mut tracer,
+            );
+            (log, tracer.finish())
         });
         handles.push(h);
     }
 
-    // let mut main_log = Log::new();
+    let mut logs = Vec::with_capacity(num_threads);
+    let mut trace = Trace::new();
     for h in handles {
-        let log = h.join().unwrap();
-        // main_log.merge(log);
+        let (log, t) = h.join().unwrap();
+        logs.push(log);
+        trace.merge(t);
     }
     // println!(
     //     "average iteration: {}",
@@ -86,42 +252,139 @@ pub fn astar<T: StateHash + Clone + Send + 'static>(
 
     // with RwLock
     let end = incumbent.read().unwrap().clone();
-    Some(end)
+    if end.f == S::Cost::MAX {
+        return (None, logs, trace);
+    }
+    (Some(end), logs, trace)
+}
+
+// Sends a successor to the thread owning its hash value.
+pub struct HashRouter<T> {
+    hasher: T,
+    num_threads: usize,
+}
+
+impl<T> HashRouter<T> {
+    pub fn new(hasher: T, num_threads: usize) -> Self {
+        HashRouter {
+            hasher,
+            num_threads,
+        }
+    }
+}
+
+impl<S: State, T: StateHasher<S>> Router<S> for HashRouter<T> {
+    fn route(&mut self, node: &Node<S>) -> usize {
+        (self.hasher.hash(&node.state) % (self.num_threads as u64)) as usize
+    }
+}
+
+// Deterministic variant of astar, threads run in lock-step rounds.
+// Routing by hash needs no randomness, so there is no seed.
+pub fn astar_lockstep<S: State, T: StateHasher<S>, H: Heuristic<S> + 'static>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
+    num_threads: usize,
+    hasher: T,
+) -> (Option<Node<S>>, Log) {
+    let routers = (0..num_threads)
+        .map(|_| HashRouter::new(hasher.clone(), num_threads))
+        .collect();
+    let (end, logs) = lockstep::astar(
+        init_state,
+        end_state,
+        Arc::new(h_func),
+        routers,
+        ROUND_LEN,
+        Arc::new(Limit::none()),
+    );
+    let mut main_log = Log::new();
+    for log in logs {
+        main_log.merge(log);
+    }
+    (end, main_log)
+}
+
+// The hash a state is routed by, from its node. Keys other than
+// node.hash are kept in the node too, set from the key of the parent.
+pub trait Route<S: State>: Clone + Send + 'static {
+    fn key(&self, node: &Node<S>) -> u64;
+    // Sets the key of the start node.
+    fn start(&self, _node: &mut Node<S>) {}
+    // Sets the key of succ, a successor of parent.
+    fn carry(&self, _parent: &Node<S>, _succ: &mut Node<S>) {}
+}
+
+// Routes by the hash that keys the tables.
+#[derive(Clone)]
+pub struct ByHash;
+
+impl<S: State> Route<S> for ByHash {
+    fn key(&self, node: &Node<S>) -> u64 {
+        node.hash
+    }
 }
 
-pub fn search<T: StateHash>(
-    start_state: &Grid,
-    end_state: &Grid,
-    incumbent: Arc<RwLock<Node>>,
+// Routes by the hash under an abstract hasher, kept in
+// node.abstract_hash.
+#[derive(Clone)]
+pub struct ByAbstract<A>(pub A);
+
+impl<S: State, A: StateHasher<S>> Route<S> for ByAbstract<A> {
+    fn key(&self, node: &Node<S>) -> u64 {
+        node.abstract_hash
+    }
+
+    fn start(&self, node: &mut Node<S>) {
+        node.abstract_hash = self.0.hash(&node.state);
+    }
+
+    fn carry(&self, parent: &Node<S>, succ: &mut Node<S>) {
+        succ.abstract_hash =
+            self.0
+                .successor_hash(&parent.state, parent.abstract_hash, &succ.state);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn search<S: State, T: StateHasher<S>, R: Route<S>, H: Heuristic<S> + ?Sized>(
+    start_state: &S,
+    end_state: &S,
+    incumbent: Arc<RwLock<Node<S>>>,
     num_threads: i32,
-    h_func: fn(&Grid, &Grid) -> i32,
+    h_func: &H,
     thread_num: i32,
-    rx: Receiver<Node>,
-    senders: Vec<Sender<Node>>,
-    msg_sent: Arc<AtomicU64>,
-    msg_recv: Arc<AtomicU64>,
+    rx: Receiver<Node<S>>,
+    senders: Vec<Sender<Node<S>>>,
+    pending: Arc<AtomicU64>,
     term: Arc<AtomicBool>,
     hasher: T,
+    route: R,
+    limit: Arc<Limit>,
+    tracer: &mut Tracer,
 ) -> Log {
     // let mut first_iteration = true;
-    let mut buffer: BinaryHeap<Node> = BinaryHeap::new();
-    let mut queue: BinaryHeap<Node> = BinaryHeap::new();
-    let mut open_states: HashMap<Grid, i32> = HashMap::new(); // map grid -> f
-    let mut closed_states: HashMap<Grid, i32> = HashMap::new(); // map grid -> f
+    let mut buffer: BinaryHeap<Node<S>> = BinaryHeap::new();
+    let mut queue: BinaryHeap<Node<S>> = BinaryHeap::new();
+    // keyed by the hashes in the nodes, which come from hasher
+    let mut open_states: StateMap<S, S::Cost> = StateMap::new(); // map grid -> f
+    let mut closed_states: StateMap<S, S::Cost> = StateMap::new(); // map grid -> g
     let mut log = Log::new();
+    let mut idle = false;
 
     // Initialization
     let mut start = Node::new(start_state.clone());
     start.calc_cost(end_state, h_func);
-    open_states.insert(start.state.clone(), start.f);
+    start.hash = hasher.hash(&start.state);
+    route.start(&mut start);
+    open_states.insert(start.hash, start.state.clone(), start.f);
     queue.push(start);
 
     loop {
         log.iter_cnt += 1;
         // Termination detection
         if term.load(Ordering::SeqCst) {
-            // println!("sent: {}", msg_sent.load(Ordering::SeqCst));
-            // println!("received: {}", msg_recv.load(Ordering::SeqCst));
             // println!("#iter: {}", log.iter_cnt);
             break;
         }
@@ -130,7 +393,12 @@ pub fn search<T: StateHash>(
         if log.iter_cnt % 2 == 0 {
             loop {
                 if let Ok(msg) = rx.try_recv() {
-                    // msg_recv.fetch_add(1, Ordering::SeqCst);
+                    if idle {
+                        idle = false;
+                    } else {
+                        pending.fetch_sub(1, Ordering::SeqCst);
+                    }
+                    tracer.instant(EventKind::Recv, msg.f.to_f64());
                     buffer.push(msg);
                     continue;
                 }
@@ -143,79 +411,103 @@ pub fn search<T: StateHash>(
             let node = buffer.pop().unwrap();
 
             // if incoming node is in closed states
-            if let Some(&g) = closed_states.get(&node.state) {
-                if g > node.g {
+            if let Some(&g) = closed_states.get(node.hash, &node.state) {
+                if node.g.improves(g) {
                     // reopen state if it has lower cost.
-                    closed_states.remove(&node.state);
+                    closed_states.remove(node.hash, &node.state);
                 } else {
                     continue;
                 }
             }
 
             // If incoming node is in open states
-            if open_states.contains_key(&node.state) {
+            if let Some(&f) = open_states.get(node.hash, &node.state) {
                 // skip if node has higher cost
-                if node.f >= *open_states.get(&node.state).unwrap() {
+                if !node.f.improves(f) {
                     continue;
                 }
             }
 
             // incoming node either not exists in open states
             // or has lower cost
-            open_states
-                .entry(node.state.clone())
-                .and_modify(|f| *f = node.f)
-                .or_insert(node.f);
+            open_states.insert(node.hash, node.state.clone(), node.f);
             queue.push(node);
         }
 
         // Expand node from local queue
         // skip if open_states is empty or local node is worse than incumbent
-        if open_states.is_empty() || queue.peek().unwrap().f >= incumbent.read().unwrap().f {
+        if open_states.is_empty()
+            || !queue
+                .peek()
+                .unwrap()
+                .f
+                .improves(incumbent.read().unwrap().f)
+        {
             log.abort_cnt += 1;
+            if limit.reached() {
+                term.store(true, Ordering::SeqCst);
+            }
+            if !idle {
+                idle = true;
+                pending.fetch_sub(1, Ordering::SeqCst);
+            }
+            if pending.load(Ordering::SeqCst) == 0 {
+                break;
+            }
+            tracer.idle();
             continue;
         }
+        tracer.busy();
 
         let mut node;
         // nodes in local queue may no longer exist in open states
         loop {
             node = queue.pop().unwrap();
-            if open_states.contains_key(&node.state) {
+            if open_states.contains(node.hash, &node.state) {
                 break;
             }
         }
 
-        open_states.remove(&node.state);
-        closed_states.insert(node.state.clone(), node.g);
+        open_states.remove(node.hash, &node.state);
+        closed_states.insert(node.hash, node.state.clone(), node.g);
 
+        // A goal only bounds the search, cheaper ones may still be
+        // open elsewhere.
         if node.state == *end_state {
-            // println!("Reach end state.");
-            term.store(true, Ordering::SeqCst);
             let mut incumbent = incumbent.write().unwrap();
-            if node.f < incumbent.f {
+            if node.f.improves(incumbent.f) {
                 *incumbent = node.clone();
-                continue;
+                tracer.instant(EventKind::Incumbent, node.f.to_f64());
             }
+            continue;
         }
 
-        let successors = expand(&node, end_state, h_func);
-        for succ in successors {
-            loop {
-                let i = succ.state.hash_with(&hasher) % (num_threads as u32);
-                if i == thread_num as u32 {
-                    buffer.push(succ);
-                    break;
-                }
-                match senders[i as usize].send(succ.clone()) {
-                    Ok(_) => {
-                        log.node_cnt += 1;
-                        // msg_sent.fetch_add(1, Ordering::SeqCst);
-                        break;
-                    }
-                    Err(_) => continue,
-                };
+        let expand_start = tracer.now();
+        let successors = expand_hashed(&node, end_state, h_func, &hasher);
+        log.exp_cnt += 1;
+        log.gen_cnt += successors.len() as i32;
+        if limit.tick() {
+            term.store(true, Ordering::SeqCst);
+        }
+        for mut succ in successors {
+            route.carry(&node, &mut succ);
+            let i = route.key(&succ) % (num_threads as u64);
+            if i == thread_num as u64 {
+                buffer.push(succ);
+                continue;
+            }
+            // Counted before it leaves, the owner may be done with it
+            // before send returns.
+            pending.fetch_add(1, Ordering::SeqCst);
+            // Sending only fails once the owner terminated, the node can be dropped then.
+            if senders[i as usize].send(succ).is_ok() {
+                log.node_cnt += 1;
+                tracer.instant(EventKind::Send, i as f64);
+            } else {
+                pending.fetch_sub(1, Ordering::SeqCst);
             }
         }
+        tracer.span(EventKind::Expand, expand_start, node.f.to_f64());
     }
     log
 }
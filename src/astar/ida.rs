@@ -0,0 +1,437 @@
+// Parallel IDA* with a shared transposition table.
+// Every iteration is a depth first search bounded by f, the bound of the
+// next iteration is the least f above the bound that any thread saw.
+// The threads share
+// - a table from states to the least g they were reached with in this
+//   iteration. A state reached again with a g that is not smaller is not
+//   searched again, by any thread, so transpositions and cycles are
+//   searched once. The table takes no locks: it is open addressing over
+//   slots whose hash and g are atomics. A thread claims a free slot with a
+//   compare and swap on its hash and lowers g with an atomic min, states
+//   with the same hash are compared in full. A state that finds no slot
+//   within a few probes is searched but not recorded.
+// - a pool of work. A thread that runs out of work waits in the pool,
+//   and a busy thread that sees it waiting splits its stack: it gives
+//   away half of the untried children of its shallowest frame.
+// The iteration ends when all threads wait and the pool is empty.
+// Moves back to the parent are not made.
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::thread;
+
+use crate::utils::cost::Cost;
+use crate::utils::helpers::expand_hashed;
+use crate::utils::heuristic::Heuristic;
+use crate::utils::structs::{Limit, Log, Node, State, StateHasher};
+
+// log2 of the states the transposition table holds.
+pub const TABLE_BITS: u32 = 20;
+// Slots a state is looked for in, from the one of its hash on.
+const PROBES: usize = 32;
+
+pub fn astar<S: State, T: StateHasher<S>, H: Heuristic<S>>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
+    num_threads: usize,
+    hasher: T,
+) -> Option<Node<S>> {
+    let limit = Limit::none();
+    solve(init_state, end_state, &h_func, num_threads, hasher, &limit).0
+}
+
+// One log per thread.
+pub fn solve<S: State, T: StateHasher<S>, H: Heuristic<S> + ?Sized>(
+    init_state: &S,
+    end_state: &S,
+    h_func: &H,
+    num_threads: usize,
+    hasher: T,
+    limit: &Limit,
+) -> (Option<Node<S>>, Vec<Log>) {
+    let num_threads = num_threads.max(1);
+    let mut logs = vec![Log::new(); num_threads];
+    let mut table = Table::new(TABLE_BITS);
+    let mut start = Node::new(init_state.clone());
+    start.calc_cost(end_state, h_func);
+    start.hash = hasher.hash(init_state);
+    let mut bound = start.f;
+
+    loop {
+        let shared = Shared {
+            end_state,
+            h_func,
+            hasher: &hasher,
+            limit,
+            table: &table,
+            bound,
+            next: Mutex::new(S::Cost::MAX),
+            found: Mutex::new(None),
+            stop: AtomicBool::new(false),
+            pool: Pool::new(num_threads),
+        };
+        shared.pool.give(vec![Work {
+            node: start.clone(),
+            parent: None,
+        }]);
+        let iteration: Vec<Log> = thread::scope(|s| {
+            let handles: Vec<_> = (0..num_threads).map(|_| s.spawn(|| shared.run())).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        for (log, l) in logs.iter_mut().zip(iteration) {
+            log.merge(l);
+        }
+
+        let found = shared.found.into_inner().unwrap();
+        let next = shared.next.into_inner().unwrap();
+        if found.is_some() || limit.reached() || next == S::Cost::MAX {
+            return (found, logs);
+        }
+        bound = next;
+        table.clear();
+    }
+}
+
+// A subtree to search.
+struct Work<S: State> {
+    node: Node<S>,
+    parent: Option<S>,
+}
+
+struct Frame<S: State> {
+    state: S,
+    children: Vec<Node<S>>,
+}
+
+struct Slot<S> {
+    // hash of the state, 0 while the slot is free
+    key: AtomicU64,
+    // set right after the key by the thread that claimed the slot
+    state: OnceLock<Box<S>>,
+    // least g the state was reached with, as Cost::to_bits
+    g: AtomicU64,
+}
+
+struct Table<S: State> {
+    slots: Vec<Slot<S>>,
+}
+
+impl<S: State> Table<S> {
+    fn new(bits: u32) -> Self {
+        Table {
+            slots: (0..1usize << bits.min(32))
+                .map(|_| Slot {
+                    key: AtomicU64::new(0),
+                    state: OnceLock::new(),
+                    g: AtomicU64::new(u64::MAX),
+                })
+                .collect(),
+        }
+    }
+
+    fn clear(&mut self) {
+        for slot in self.slots.iter_mut() {
+            if *slot.key.get_mut() != 0 {
+                *slot.key.get_mut() = 0;
+                slot.state.take();
+                *slot.g.get_mut() = u64::MAX;
+            }
+        }
+    }
+
+    // Records that the state of node was reached with its g,
+    // false if it was reached with g or less before.
+    fn visit(&self, node: &Node<S>) -> bool {
+        // 0 marks a free slot, the hash 0 shares the key 1
+        let key = node.hash.max(1);
+        let g = node.g.to_bits();
+        let mask = self.slots.len() - 1;
+        let mixed = (key ^ key >> 32).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let mut i = (mixed >> 32) as usize & mask;
+        for _ in 0..PROBES.min(self.slots.len()) {
+            let slot = &self.slots[i];
+            let mut found = slot.key.load(Ordering::Acquire);
+            if found == 0 {
+                match slot
+                    .key
+                    .compare_exchange(0, key, Ordering::AcqRel, Ordering::Acquire)
+                {
+                    Ok(_) => {
+                        let _ = slot.state.set(Box::new(node.state.clone()));
+                        slot.g.fetch_min(g, Ordering::AcqRel);
+                        return true;
+                    }
+                    Err(k) => found = k,
+                }
+            }
+            // the claimer sets the state right after the key
+            if found == key && **slot.state.wait() == node.state {
+                return slot.g.fetch_min(g, Ordering::AcqRel) > g;
+            }
+            i = (i + 1) & mask;
+        }
+        true
+    }
+}
+
+struct PoolState<S: State> {
+    work: Vec<Work<S>>,
+    waiting: usize,
+    done: bool,
+}
+
+struct Pool<S: State> {
+    state: Mutex<PoolState<S>>,
+    ready: Condvar,
+    // a thread waits for work and the pool is empty
+    hungry: AtomicBool,
+    num_threads: usize,
+}
+
+impl<S: State> Pool<S> {
+    fn new(num_threads: usize) -> Self {
+        Pool {
+            state: Mutex::new(PoolState {
+                work: vec![],
+                waiting: 0,
+                done: false,
+            }),
+            ready: Condvar::new(),
+            hungry: AtomicBool::new(false),
+            num_threads,
+        }
+    }
+
+    fn give(&self, work: Vec<Work<S>>) {
+        let mut p = self.state.lock().unwrap();
+        p.work.extend(work);
+        self.hungry.store(false, Ordering::Relaxed);
+        self.ready.notify_all();
+    }
+
+    // Waits for work, None once the iteration is over.
+    fn take(&self) -> Option<Work<S>> {
+        let mut p = self.state.lock().unwrap();
+        p.waiting += 1;
+        loop {
+            if p.done {
+                return None;
+            }
+            if let Some(work) = p.work.pop() {
+                p.waiting -= 1;
+                self.hungry
+                    .store(p.waiting > 0 && p.work.is_empty(), Ordering::Relaxed);
+                return Some(work);
+            }
+            if p.waiting == self.num_threads {
+                p.done = true;
+                self.ready.notify_all();
+                return None;
+            }
+            self.hungry.store(true, Ordering::Relaxed);
+            p = self.ready.wait(p).unwrap();
+        }
+    }
+
+    fn finish(&self) {
+        self.state.lock().unwrap().done = true;
+        self.ready.notify_all();
+    }
+}
+
+// What the threads of one iteration share.
+struct Shared<'a, S: State, T, H: ?Sized> {
+    end_state: &'a S,
+    h_func: &'a H,
+    hasher: &'a T,
+    limit: &'a Limit,
+    table: &'a Table<S>,
+    bound: S::Cost,
+    // least f above the bound
+    next: Mutex<S::Cost>,
+    found: Mutex<Option<Node<S>>>,
+    stop: AtomicBool,
+    pool: Pool<S>,
+}
+
+impl<S: State, T: StateHasher<S>, H: Heuristic<S> + ?Sized> Shared<'_, S, T, H> {
+    fn run(&self) -> Log {
+        let mut log = Log::new();
+        let mut next = S::Cost::MAX;
+        while let Some(work) = self.pool.take() {
+            self.search(work, &mut next, &mut log);
+        }
+        let mut shared = self.next.lock().unwrap();
+        *shared = (*shared).min(next);
+        log
+    }
+
+    fn search(&self, work: Work<S>, next: &mut S::Cost, log: &mut Log) {
+        let mut stack: Vec<Frame<S>> = vec![];
+        self.visit(work.node, work.parent.as_ref(), &mut stack, next, log);
+        while let Some(frame) = stack.last_mut() {
+            if self.stop.load(Ordering::Relaxed) {
+                return;
+            }
+            let child = match frame.children.pop() {
+                Some(child) => child,
+                None => {
+                    stack.pop();
+                    continue;
+                }
+            };
+            let parent = frame.state.clone();
+            self.visit(child, Some(&parent), &mut stack, next, log);
+            if self.pool.hungry.load(Ordering::Relaxed) {
+                self.split(&mut stack);
+            }
+        }
+    }
+
+    // Pushes the frame of node if it is to be expanded.
+    fn visit(
+        &self,
+        node: Node<S>,
+        parent: Option<&S>,
+        stack: &mut Vec<Frame<S>>,
+        next: &mut S::Cost,
+        log: &mut Log,
+    ) {
+        log.iter_cnt += 1;
+        if self.bound < node.f {
+            *next = (*next).min(node.f);
+            return;
+        }
+        if node.state == *self.end_state {
+            let mut found = self.found.lock().unwrap();
+            if found.as_ref().is_none_or(|f| node.g < f.g) {
+                *found = Some(node);
+            }
+            self.stop();
+            return;
+        }
+        if !self.table.visit(&node) {
+            log.abort_cnt += 1;
+            return;
+        }
+        if self.limit.tick() {
+            self.stop();
+            return;
+        }
+
+        log.exp_cnt += 1;
+        let children: Vec<Node<S>> = expand_hashed(&node, self.end_state, self.h_func, self.hasher)
+            .into_iter()
+            .filter(|child| Some(&child.state) != parent)
+            .collect();
+        log.gen_cnt += children.len() as i32;
+        stack.push(Frame {
+            state: node.state,
+            children,
+        });
+    }
+
+    // Gives half of the untried children of the shallowest frame
+    // that has any to the pool.
+    fn split(&self, stack: &mut [Frame<S>]) {
+        let frame = match stack.iter_mut().find(|f| !f.children.is_empty()) {
+            Some(frame) => frame,
+            None => return,
+        };
+        let half = frame.children.len().div_ceil(2);
+        let work = frame
+            .children
+            .drain(..half)
+            .map(|node| Work {
+                node,
+                parent: Some(frame.state.clone()),
+            })
+            .collect();
+        self.pool.give(work);
+    }
+
+    fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.pool.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::tests::instances;
+    use crate::utils::helpers::weighted_man_dist;
+    use crate::utils::structs::{Grid, ZHasher};
+
+    // Hashes every state to the same key, so the table sees nothing
+    // but collisions.
+    #[derive(Clone)]
+    struct Collide;
+
+    impl StateHasher<Grid> for Collide {
+        fn hash(&self, _: &Grid) -> u64 {
+            0
+        }
+    }
+
+    fn check<T: StateHasher<Grid>>(hasher: T) {
+        for (start, end, best) in instances(4, 4, 40) {
+            for threads in [1, 3] {
+                let node = astar(&start, &end, weighted_man_dist, threads, hasher.clone());
+                assert_eq!(node.unwrap().g, best, "{} threads from {}", threads, start);
+            }
+        }
+    }
+
+    #[test]
+    fn costs_match_seq() {
+        check(ZHasher::<u32>::new(4, 4));
+    }
+
+    #[test]
+    fn costs_match_seq_on_collisions() {
+        check(Collide);
+    }
+
+    #[test]
+    fn table_keeps_the_least_g() {
+        let mut table = Table::new(4);
+        let end = Grid::rand_with_seed(3, 0);
+        let states: Vec<Node<Grid>> = (0..6)
+            .map(|seed| {
+                let mut node = Node::new(end.rand_actions_with_seed(20, seed));
+                node.hash = 7;
+                node.g = 5;
+                node
+            })
+            .collect();
+        // the first visit of each state wins, from any thread
+        let first: usize = thread::scope(|s| {
+            let handles: Vec<_> = (0..3)
+                .map(|_| s.spawn(|| states.iter().filter(|n| table.visit(n)).count()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        let distinct = (0..6)
+            .filter(|&i| !states[..i].iter().any(|n| n.state == states[i].state))
+            .count();
+        assert_eq!(first, distinct);
+        let mut better = states[0].clone();
+        better.g = 4;
+        assert!(table.visit(&better));
+        assert!(!table.visit(&better));
+        assert!(!table.visit(&states[0]));
+        table.clear();
+        assert!(table.visit(&states[0]));
+    }
+
+    #[test]
+    fn full_table_searches_on() {
+        let table = Table::new(1);
+        let end = Grid::rand_with_seed(3, 0);
+        for seed in 0..4 {
+            let node = Node::new(end.rand_actions_with_seed(20, seed));
+            assert!(table.visit(&node));
+        }
+    }
+}
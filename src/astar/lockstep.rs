@@ -0,0 +1,299 @@
+// Deterministic parallel search.
+// Threads advance in lock-step rounds: every thread expands at most
+// round_len nodes, then all threads meet at a barrier and exchange
+// the messages of the round in a fixed order. Nothing depends on the
+// OS scheduler, so the expansion order and the logs are the same on
+// every run with the same thread count.
+use crossbeam::channel::{Receiver, Sender};
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+
+use crate::utils::cost::Cost;
+use crate::utils::helpers::expand;
+use crate::utils::heuristic::Heuristic;
+use crate::utils::structs::{Limit, Log, Node, State};
+
+// Decides which thread owns a successor.
+pub trait Router<S: State> {
+    fn route(&mut self, node: &Node<S>) -> usize;
+}
+
+// A message tagged with its sender and sequence number,
+// used to sort the messages of a round.
+struct Msg<S: State> {
+    from: usize,
+    seq: u64,
+    node: Node<S>,
+}
+
+// What a thread reports at the end of a round.
+#[derive(Clone)]
+struct Slot<S: State> {
+    best: Option<Node<S>>,
+    min_f: S::Cost,
+    stop: bool,
+}
+
+pub fn astar<S: State, H: Heuristic<S> + ?Sized + 'static, R: Router<S> + Send + 'static>(
+    init_state: &S,
+    end_state: &S,
+    h_func: Arc<H>,
+    routers: Vec<R>,
+    round_len: usize,
+    limit: Arc<Limit>,
+) -> (Option<Node<S>>, Vec<Log>) {
+    let num_threads = routers.len();
+    let barrier = Arc::new(Barrier::new(num_threads));
+    let slots = Arc::new(Mutex::new(vec![
+        Slot {
+            best: None,
+            min_f: S::Cost::MAX,
+            stop: false,
+        };
+        num_threads
+    ]));
+
+    let mut senders = Vec::with_capacity(num_threads);
+    let mut receivers = Vec::with_capacity(num_threads);
+    for _ in 0..num_threads {
+        let (s, r) = crossbeam::channel::unbounded();
+        senders.push(s);
+        receivers.push(r);
+    }
+
+    let mut handles = Vec::with_capacity(num_threads);
+    for (i, router) in routers.into_iter().enumerate() {
+        let init_state = init_state.clone();
+        let end_state = end_state.clone();
+        let senders = senders.clone();
+        let rx = receivers.remove(0);
+        let barrier = barrier.clone();
+        let slots = slots.clone();
+        let limit = limit.clone();
+        let h_func = h_func.clone();
+        let h = thread::spawn(move || {
+            search(
+                &init_state,
+                &end_state,
+                &*h_func,
+                i,
+                router,
+                round_len,
+                rx,
+                senders,
+                barrier,
+                slots,
+                limit,
+            )
+        });
+        handles.push(h);
+    }
+
+    let mut logs = Vec::with_capacity(num_threads);
+    let mut end = None;
+    for h in handles {
+        let (best, log) = h.join().unwrap();
+        logs.push(log);
+        end = end.or(best);
+    }
+    (end, logs)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn search<S: State, H: Heuristic<S> + ?Sized, R: Router<S>>(
+    start_state: &S,
+    end_state: &S,
+    h_func: &H,
+    thread_num: usize,
+    mut router: R,
+    round_len: usize,
+    rx: Receiver<Msg<S>>,
+    senders: Vec<Sender<Msg<S>>>,
+    barrier: Arc<Barrier>,
+    slots: Arc<Mutex<Vec<Slot<S>>>>,
+    limit: Arc<Limit>,
+) -> (Option<Node<S>>, Log) {
+    let mut buffer: BinaryHeap<Node<S>> = BinaryHeap::new();
+    let mut queue: BinaryHeap<Node<S>> = BinaryHeap::new();
+    let mut open_states: HashMap<S, S::Cost> = HashMap::new(); // map grid -> f
+    let mut closed_states: HashMap<S, S::Cost> = HashMap::new(); // map grid -> g
+    let mut log = Log::new();
+    let mut seq = 0;
+    let mut local_best: Option<Node<S>> = None;
+    let mut incumbent: Option<Node<S>> = None;
+
+    // Thread 0 seeds the search, the others wait for messages.
+    if thread_num == 0 {
+        let mut start = Node::new(start_state.clone());
+        start.calc_cost(end_state, h_func);
+        buffer.push(start);
+    }
+
+    loop {
+        // Handle the messages of the previous round
+        while let Some(node) = buffer.pop() {
+            if let Some(&g) = closed_states.get(&node.state) {
+                if node.g.improves(g) {
+                    closed_states.remove(&node.state);
+                } else {
+                    continue;
+                }
+            }
+            if let Some(&f) = open_states.get(&node.state) {
+                if !node.f.improves(f) {
+                    continue;
+                }
+            }
+            open_states.insert(node.state.clone(), node.f);
+            queue.push(node);
+        }
+
+        // Expand up to round_len nodes
+        let bound = incumbent.as_ref().map_or(S::Cost::MAX, |n| n.f);
+        for _ in 0..round_len {
+            log.iter_cnt += 1;
+            // drop nodes that are no longer open
+            while let Some(n) = queue.peek() {
+                if open_states.get(&n.state) == Some(&n.f) {
+                    break;
+                }
+                queue.pop();
+            }
+            if queue.peek().is_none_or(|n| !n.f.improves(bound)) {
+                log.abort_cnt += 1;
+                break;
+            }
+
+            let node = queue.pop().unwrap();
+            open_states.remove(&node.state);
+            closed_states.insert(node.state.clone(), node.g);
+
+            if node.state == *end_state {
+                if local_best.as_ref().is_none_or(|b| node.f.improves(b.f)) {
+                    local_best = Some(node);
+                }
+                continue;
+            }
+
+            let successors = expand(&node, end_state, h_func);
+            log.exp_cnt += 1;
+            log.gen_cnt += successors.len() as i32;
+            limit.tick();
+            for succ in successors {
+                let i = router.route(&succ);
+                if i == thread_num {
+                    buffer.push(succ);
+                } else {
+                    seq += 1;
+                    log.node_cnt += 1;
+                    let msg = Msg {
+                        from: thread_num,
+                        seq,
+                        node: succ,
+                    };
+                    senders[i].send(msg).unwrap();
+                }
+            }
+        }
+
+        // All messages of the round are sent past this point
+        barrier.wait();
+        let mut msgs: Vec<Msg<S>> = rx.try_iter().collect();
+        msgs.sort_by_key(|m| (m.from, m.seq));
+        for m in msgs {
+            buffer.push(m.node);
+        }
+        let min_f = queue
+            .peek()
+            .map_or(S::Cost::MAX, |n| n.f)
+            .min(buffer.peek().map_or(S::Cost::MAX, |n| n.f));
+        slots.lock().unwrap()[thread_num] = Slot {
+            best: local_best.clone(),
+            min_f,
+            // every tick of the round is counted at this point
+            stop: limit.reached(),
+        };
+
+        // All slots are written past this point, every thread
+        // reduces them to the same incumbent and decision.
+        barrier.wait();
+        let reports = slots.lock().unwrap().clone();
+        for slot in reports.iter() {
+            if let Some(b) = &slot.best {
+                if incumbent.as_ref().is_none_or(|n| b.f.improves(n.f)) {
+                    incumbent = Some(b.clone());
+                }
+            }
+        }
+        let bound = incumbent.as_ref().map_or(S::Cost::MAX, |n| n.f);
+        if reports.iter().all(|s| !s.min_f.improves(bound)) {
+            break;
+        }
+        if reports.iter().any(|s| s.stop) {
+            break;
+        }
+    }
+
+    // Only thread 0 reports the solution
+    if thread_num == 0 {
+        (incumbent, log)
+    } else {
+        (None, log)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::astar::hda::HashRouter;
+    use crate::astar::{dpa, seq};
+    use crate::utils::helpers::{derive_seed, weighted_man_dist};
+    use crate::utils::structs::{Grid, ZHasher};
+
+    fn run<R: Router<Grid> + Send + 'static>(
+        start: &Grid,
+        end: &Grid,
+        routers: Vec<R>,
+    ) -> (Option<Node<Grid>>, Vec<Log>) {
+        astar(
+            start,
+            end,
+            Arc::new(weighted_man_dist),
+            routers,
+            dpa::ROUND_LEN,
+            Arc::new(Limit::none()),
+        )
+    }
+
+    // Same logs on every run, and the cost of the sequential search.
+    fn check_repeats<R: Router<Grid> + Send + 'static>(make: impl Fn() -> Vec<R>) {
+        let end = Grid::rand_with_seed(4, 69);
+        for seed in 0..3 {
+            let start = end.rand_actions_with_seed(40, seed);
+            let cost = seq::astar(&start, &end, weighted_man_dist).unwrap().g;
+            let (first, logs) = run(&start, &end, make());
+            assert_eq!(first.unwrap().g, cost);
+            for _ in 0..3 {
+                let (node, again) = run(&start, &end, make());
+                assert_eq!(node.unwrap().g, cost);
+                assert_eq!(again, logs);
+            }
+        }
+    }
+
+    #[test]
+    fn dpa_repeats() {
+        check_repeats(|| {
+            (0..4)
+                .map(|i| dpa::RandRouter::new(derive_seed(7, i as u64), 0.5, i, 4))
+                .collect()
+        });
+    }
+
+    #[test]
+    fn hda_repeats() {
+        let hasher = ZHasher::<u32>::new(4, 4);
+        check_repeats(|| (0..4).map(|_| HashRouter::new(hasher.clone(), 4)).collect());
+    }
+}
@@ -1,3 +1,10 @@
+pub mod distributed;
 pub mod dpa;
+pub mod external;
+pub mod frontier;
 pub mod hda;
+pub mod ida;
+pub mod lockstep;
+pub mod rbfs;
 pub mod seq;
+pub mod sma;
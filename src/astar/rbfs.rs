@@ -0,0 +1,112 @@
+// Recursive best-first search (Korf 1993), A* order in memory linear in
+// the depth of the solution. Every call explores the best child as long as
+// its f stays below the best alternative, then returns with the child's
+// backed up f, which the child starts from when it is explored again.
+// Moves back to the parent are not made.
+use crate::utils::cost::Cost;
+use crate::utils::helpers::expand;
+use crate::utils::heuristic::Heuristic;
+use crate::utils::structs::{Limit, Log, Node, State};
+
+pub fn astar<S: State, H: Heuristic<S>>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
+) -> Option<Node<S>> {
+    solve(init_state, end_state, &h_func, &Limit::none()).0
+}
+
+pub fn solve<S: State, H: Heuristic<S> + ?Sized>(
+    init_state: &S,
+    end_state: &S,
+    h_func: &H,
+    limit: &Limit,
+) -> (Option<Node<S>>, Log) {
+    let mut start = Node::new(init_state.clone());
+    start.calc_cost(end_state, h_func);
+    let mut rbfs = Rbfs {
+        end_state,
+        h_func,
+        limit,
+        log: Log::new(),
+    };
+    let (node, _) = rbfs.search(&start, None, start.f, S::Cost::MAX);
+    (node, rbfs.log)
+}
+
+struct Rbfs<'a, S: State, H: ?Sized> {
+    end_state: &'a S,
+    h_func: &'a H,
+    limit: &'a Limit,
+    log: Log,
+}
+
+impl<S: State, H: Heuristic<S> + ?Sized> Rbfs<'_, S, H> {
+    // Returns the goal if it is found below node without passing bound,
+    // otherwise the backed up f of node.
+    fn search(
+        &mut self,
+        node: &Node<S>,
+        parent: Option<&S>,
+        stored_f: S::Cost,
+        bound: S::Cost,
+    ) -> (Option<Node<S>>, S::Cost) {
+        self.log.iter_cnt += 1;
+        if node.state == *self.end_state {
+            return (Some(node.clone()), node.f);
+        }
+        if self.limit.tick() {
+            return (None, S::Cost::MAX);
+        }
+
+        self.log.exp_cnt += 1;
+        let mut children: Vec<(Node<S>, S::Cost)> = expand(node, self.end_state, self.h_func)
+            .into_iter()
+            .filter(|child| Some(&child.state) != parent)
+            .map(|child| {
+                // A node explored before passes its backed up f on.
+                let f = if node.f < stored_f {
+                    child.f.max(stored_f)
+                } else {
+                    child.f
+                };
+                (child, f)
+            })
+            .collect();
+        self.log.gen_cnt += children.len() as i32;
+
+        loop {
+            children.sort_by_key(|(_, f)| *f);
+            let best_f = match children.first() {
+                Some((_, f)) => *f,
+                None => return (None, S::Cost::MAX),
+            };
+            if best_f == S::Cost::MAX || bound < best_f {
+                return (None, best_f);
+            }
+            let alt = children.get(1).map_or(S::Cost::MAX, |(_, f)| *f);
+            let (found, f) = self.search(&children[0].0, Some(&node.state), best_f, bound.min(alt));
+            children[0].1 = f;
+            if found.is_some() {
+                return (found, f);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::tests::instances;
+    use crate::utils::helpers::weighted_man_dist;
+
+    #[test]
+    fn costs_match_seq() {
+        for size in [3, 4] {
+            for (start, end, best) in instances(size, size, 40) {
+                let node = astar(&start, &end, weighted_man_dist).unwrap();
+                assert_eq!(node.g, best, "{}", start);
+            }
+        }
+    }
+}
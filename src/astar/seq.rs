@@ -1,13 +1,39 @@
+use crate::utils::cost::Cost;
 use crate::utils::helpers::expand;
-use crate::utils::structs::{Grid, Log, Node};
+use crate::utils::heuristic::Heuristic;
+use crate::utils::structs::{Limit, Log, Node, State};
 use std::collections::{BinaryHeap, HashMap};
 
-pub fn astar(init_state: &Grid, end_state: &Grid, h_func: fn(&Grid, &Grid) -> i32) -> Option<Node> {
+pub fn astar<S: State, H: Heuristic<S>>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
+) -> Option<Node<S>> {
+    astar_with_log(init_state, end_state, h_func).0
+}
+
+// Same as astar, but also reports the search statistics.
+// Unlike wall time, the counters do not depend on the machine or its load.
+pub fn astar_with_log<S: State, H: Heuristic<S>>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
+) -> (Option<Node<S>>, Log) {
+    solve(init_state, end_state, &h_func, &Limit::none())
+}
+
+// Search within a budget, gives up with None once it is exhausted.
+pub fn solve<S: State, H: Heuristic<S> + ?Sized>(
+    init_state: &S,
+    end_state: &S,
+    h_func: &H,
+    limit: &Limit,
+) -> (Option<Node<S>>, Log) {
     let mut log = Log::new();
     let mut start = Node::new(init_state.clone());
     start.calc_cost(end_state, h_func);
-    let mut open: BinaryHeap<Node> = BinaryHeap::new();
-    let mut closed: HashMap<Grid, Node> = HashMap::new();
+    let mut open: BinaryHeap<Node<S>> = BinaryHeap::new();
+    let mut closed: HashMap<S, Node<S>> = HashMap::new();
     open.push(start);
 
     while !open.is_empty() {
@@ -16,16 +42,21 @@ pub fn astar(init_state: &Grid, end_state: &Grid, h_func: fn(&Grid, &Grid) -> i3
         // println!("pop n, f: {}, h: {}", n.f, n.h);
         if n.state == *end_state {
             // println!("#iter: {}", log.iter_cnt);
-            return Some(n);
+            return (Some(n), log);
         }
 
         let nodes = expand(&n, end_state, h_func);
+        log.exp_cnt += 1;
+        log.gen_cnt += nodes.len() as i32;
         closed.insert(n.state.clone(), n);
+        if limit.tick() {
+            break;
+        }
 
         for node in nodes {
             if closed.contains_key(&node.state) {
                 let closed_node = closed.get(&node.state).unwrap();
-                if node.g < closed_node.g {
+                if node.g.improves(closed_node.g) {
                     closed.remove(&node.state);
                 } else {
                     continue;
@@ -34,5 +65,5 @@ pub fn astar(init_state: &Grid, end_state: &Grid, h_func: fn(&Grid, &Grid) -> i3
             open.push(node);
         }
     }
-    None
+    (None, log)
 }
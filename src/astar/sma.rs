@@ -0,0 +1,256 @@
+// Simplified memory-bounded A* (Russell 1992) with a budget of nodes.
+// The search tree is kept in memory. When the budget is used up the worst
+// leaf, highest f and then shallowest, is forgotten and its parent
+// remembers the least f of its forgotten children. That f is backed up:
+// a parent whose children are all forgotten becomes a leaf with it, and a
+// parent with some children left is open with it until it regenerates the
+// others, which start from it.
+// Finds optimal solutions if the budget holds the path to the goal, and
+// gives up with None if it cannot.
+// Moves back to the parent are not made.
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+
+use crate::utils::cost::Cost;
+use crate::utils::heuristic::Heuristic;
+use crate::utils::structs::{Limit, Log, Node, State};
+
+// Node budget of Algorithm::Sma when none is given.
+pub const DEFAULT_NODES: usize = 1 << 20;
+
+type Key<C> = (C, Reverse<usize>, usize);
+
+struct SmaNode<S: State> {
+    state: S,
+    g: S::Cost,
+    // f of a leaf, with what was learned below it
+    f: S::Cost,
+    depth: usize,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    // least f of the children not in memory since the last expansion
+    forgotten: S::Cost,
+}
+
+// Nodes in slots, the slots of forgotten nodes are reused.
+struct Tree<S: State> {
+    slots: Vec<Option<SmaNode<S>>>,
+    free: Vec<usize>,
+    len: usize,
+    // leaves by (f, deepest first, slot)
+    leaves: BTreeSet<Key<S::Cost>>,
+    // nodes with children and forgotten children, by (forgotten, ...)
+    partial: BTreeSet<Key<S::Cost>>,
+}
+
+impl<S: State> Tree<S> {
+    fn node(&self, i: usize) -> &SmaNode<S> {
+        self.slots[i].as_ref().unwrap()
+    }
+
+    fn node_mut(&mut self, i: usize) -> &mut SmaNode<S> {
+        self.slots[i].as_mut().unwrap()
+    }
+
+    // Puts node i in the open set it belongs to, if any.
+    fn list(&mut self, i: usize) {
+        let n = self.node(i);
+        let (leaf, f, forgotten, depth) = (n.children.is_empty(), n.f, n.forgotten, n.depth);
+        if leaf {
+            self.leaves.insert((f, Reverse(depth), i));
+        } else if forgotten != S::Cost::MAX {
+            self.partial.insert((forgotten, Reverse(depth), i));
+        }
+    }
+
+    // Takes node i out of the open sets, before it changes.
+    fn unlist(&mut self, i: usize) {
+        let n = self.node(i);
+        let (f, forgotten, depth) = (n.f, n.forgotten, n.depth);
+        self.leaves.remove(&(f, Reverse(depth), i));
+        self.partial.remove(&(forgotten, Reverse(depth), i));
+    }
+
+    fn pop_best(&mut self) -> Option<Key<S::Cost>> {
+        match (self.leaves.first(), self.partial.first()) {
+            (Some(l), Some(p)) if p < l => self.partial.pop_first(),
+            (Some(_), _) => self.leaves.pop_first(),
+            (None, _) => self.partial.pop_first(),
+        }
+    }
+
+    fn add(&mut self, node: SmaNode<S>) -> usize {
+        self.len += 1;
+        let i = match self.free.pop() {
+            Some(i) => {
+                self.slots[i] = Some(node);
+                i
+            }
+            None => {
+                self.slots.push(Some(node));
+                self.slots.len() - 1
+            }
+        };
+        self.list(i);
+        i
+    }
+
+    // Forgets the worst leaf unless it is in fresh or its f is below f,
+    // true if it did. Node b is being expanded and stays out of the
+    // open sets.
+    fn forget_for(&mut self, f: S::Cost, b: usize, fresh: &[usize]) -> bool {
+        let (worst_f, depth, w) = match self.leaves.iter().rev().find(|k| !fresh.contains(&k.2)) {
+            Some(&key) if key.0 >= f => key,
+            _ => return false,
+        };
+        let p = match self.node(w).parent {
+            Some(p) => p,
+            None => return false,
+        };
+        self.leaves.remove(&(worst_f, depth, w));
+        self.slots[w] = None;
+        self.free.push(w);
+        self.len -= 1;
+        if p != b {
+            self.unlist(p);
+        }
+        let parent = self.node_mut(p);
+        parent.children.retain(|&c| c != w);
+        parent.forgotten = parent.forgotten.min(worst_f);
+        if parent.children.is_empty() {
+            parent.f = parent.forgotten;
+        }
+        if p != b {
+            self.list(p);
+        }
+        true
+    }
+}
+
+pub fn astar<S: State, H: Heuristic<S>>(
+    init_state: &S,
+    end_state: &S,
+    h_func: H,
+    max_nodes: usize,
+) -> Option<Node<S>> {
+    solve(init_state, end_state, &h_func, max_nodes, &Limit::none()).0
+}
+
+pub fn solve<S: State, H: Heuristic<S> + ?Sized>(
+    init_state: &S,
+    end_state: &S,
+    h_func: &H,
+    max_nodes: usize,
+    limit: &Limit,
+) -> (Option<Node<S>>, Log) {
+    let mut log = Log::new();
+    let max_nodes = max_nodes.max(2);
+    let mut tree = Tree {
+        slots: vec![],
+        free: vec![],
+        len: 0,
+        leaves: BTreeSet::new(),
+        partial: BTreeSet::new(),
+    };
+    tree.add(SmaNode {
+        state: init_state.clone(),
+        g: S::Cost::ZERO,
+        f: h_func.h(init_state, end_state),
+        depth: 0,
+        parent: None,
+        children: vec![],
+        forgotten: S::Cost::MAX,
+    });
+
+    while let Some((f, _, b)) = tree.pop_best() {
+        if f == S::Cost::MAX {
+            break;
+        }
+        log.iter_cnt += 1;
+        if tree.node(b).children.is_empty() && tree.node(b).state == *end_state {
+            let mut node = Node::new(end_state.clone());
+            node.g = tree.node(b).g;
+            node.calc_cost(end_state, h_func);
+            return (Some(node), log);
+        }
+        if limit.tick() {
+            break;
+        }
+
+        // The successors not in memory, all of them for a leaf.
+        log.exp_cnt += 1;
+        let n = tree.node(b);
+        let (g, depth) = (n.g, n.depth);
+        let parent = n.parent.map(|p| tree.node(p).state.clone());
+        let mut succs: Vec<(S, S::Cost, S::Cost)> = n
+            .state
+            .successors()
+            .into_iter()
+            .filter(|(s, _)| {
+                Some(s) != parent.as_ref() && n.children.iter().all(|&c| tree.node(c).state != *s)
+            })
+            .map(|(s, c)| {
+                let h = h_func.h(&s, end_state);
+                (s, g + c, (g + c + h).max(f))
+            })
+            .collect();
+        log.gen_cnt += succs.len() as i32;
+        succs.sort_by_key(|&(_, _, f)| f);
+
+        tree.node_mut(b).forgotten = S::Cost::MAX;
+        let mut fresh = vec![];
+        for (s, g, f) in succs {
+            if tree.len >= max_nodes && !tree.forget_for(f, b, &fresh) {
+                // The rest is no better, remember the best of them.
+                let node = tree.node_mut(b);
+                node.forgotten = node.forgotten.min(f);
+                break;
+            }
+            let child = tree.add(SmaNode {
+                state: s,
+                g,
+                f,
+                depth: depth + 1,
+                parent: Some(b),
+                children: vec![],
+                forgotten: S::Cost::MAX,
+            });
+            tree.node_mut(b).children.push(child);
+            fresh.push(child);
+        }
+
+        if tree.node(b).children.is_empty() {
+            // A dead end, or the budget is all on the path to b.
+            let stuck = tree.leaves.is_empty();
+            let n = tree.node_mut(b);
+            n.f = if stuck { S::Cost::MAX } else { n.forgotten };
+        }
+        tree.list(b);
+    }
+    (None, log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::tests::instances;
+    use crate::utils::helpers::weighted_man_dist;
+    use crate::utils::structs::Grid;
+
+    #[test]
+    fn costs_match_seq() {
+        for (size, max_nodes) in [(3, 64), (3, 1000), (4, 5000), (4, 1 << 20)] {
+            for (start, end, best) in instances(size, size, 40) {
+                let node = astar(&start, &end, weighted_man_dist, max_nodes).unwrap();
+                assert_eq!(node.g, best, "{} nodes from {}", max_nodes, start);
+            }
+        }
+    }
+
+    #[test]
+    fn gives_up_without_memory() {
+        let end = Grid::rand_with_seed(3, 69);
+        let start = end.rand_actions_with_seed(40, 0);
+        assert!(astar(&start, &end, weighted_man_dist, 3).is_none());
+    }
+}
@@ -0,0 +1,106 @@
+//! The error type of all fallible entry points.
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug)]
+pub enum Error {
+    /// A grid string that is not in one of the accepted layouts.
+    Parse(String),
+    /// The grid does not hold `rows * cols` cells.
+    BadLength {
+        expected: usize,
+        found: usize,
+    },
+    /// The tiles are not a permutation of `0..rows * cols - k` plus
+    /// `k >= 1` blanks.
+    NotPermutation(String),
+    /// `empty_idx` does not point at the blank.
+    BadEmptyIdx {
+        empty_idx: i32,
+        blank: usize,
+    },
+    /// An index outside of the grid.
+    OutOfBounds {
+        idx: i32,
+        len: i32,
+    },
+    /// A move that leaves the grid, starts from a cell without a blank
+    /// or runs into another blank.
+    InvalidAction((i32, i32)),
+    /// A map position that is not passable.
+    Blocked((i32, i32)),
+    /// Start and end of one instance do not belong together.
+    Mismatch(String),
+    /// The goal cannot be reached from the start.
+    NoSolution,
+    /// The expansion or time budget ran out first.
+    LimitReached,
+    /// A solver option outside of its range.
+    BadOption(String),
+    /// More cells than a rank or table can hold.
+    TooLarge(String),
+    /// A pattern that no pattern database can be built for.
+    BadPattern(String),
+    /// A file that is damaged or was written for something else.
+    Corrupt(String),
+    /// A message from another process of a distributed search that
+    /// does not follow the protocol.
+    Protocol(String),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(msg) => write!(f, "cannot parse grid: {}", msg),
+            Error::BadLength { expected, found } => {
+                write!(f, "expected {} cells, found {}", expected, found)
+            }
+            Error::NotPermutation(msg) => write!(f, "not a permutation: {}", msg),
+            Error::BadEmptyIdx { empty_idx, blank } => {
+                write!(
+                    f,
+                    "empty_idx is {}, but the blank is at {}",
+                    empty_idx, blank
+                )
+            }
+            Error::OutOfBounds { idx, len } => write!(f, "index {} out of 0..{}", idx, len),
+            Error::InvalidAction(a) => write!(f, "action {:?} moves no tile", a),
+            Error::Blocked(p) => write!(f, "cell {:?} is not passable", p),
+            Error::Mismatch(msg) => write!(f, "start and end do not match: {}", msg),
+            Error::NoSolution => write!(f, "no solution"),
+            Error::LimitReached => write!(f, "search limit reached"),
+            Error::BadOption(msg) => write!(f, "bad option: {}", msg),
+            Error::TooLarge(msg) => write!(f, "too large: {}", msg),
+            Error::BadPattern(msg) => write!(f, "bad pattern: {}", msg),
+            Error::Corrupt(msg) => write!(f, "corrupt file: {}", msg),
+            Error::Protocol(msg) => write!(f, "protocol error: {}", msg),
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Json(e) => write!(f, "json error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
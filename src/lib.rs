@@ -0,0 +1,18 @@
+//! Sequential and parallel A* (DPA*, HDA*) for sliding tile puzzles
+//! and grid map pathfinding (`utils::map`). Searches run on any
+//! [`State`].
+//!
+//! The [`Solver`] builder is the stable entry point. The `astar` and
+//! `utils` modules expose the individual searches and their building
+//! blocks for experiments, their signatures may change between versions.
+pub mod astar;
+pub mod error;
+pub mod solver;
+pub mod utils;
+
+pub use error::{Error, Result};
+pub use solver::{Algorithm, Solution, Solver};
+pub use utils::cost::{Cost, OrdF64};
+pub use utils::helpers::{man_dist, misplaced, weighted_man_dist};
+pub use utils::heuristic::{Heuristic, ManDist, PartialManDist};
+pub use utils::structs::{Grid, KeyWidth, Keys, Log, Node, State, StateHasher, TileCost};
@@ -1,212 +1,433 @@
-#![allow(dead_code)]
-#![allow(unused_variables)]
-#![allow(unused_imports)]
-use rand::{Rng, SeedableRng};
-use std::collections::BinaryHeap;
-use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-mod astar;
-mod utils;
+use astar::astar::distributed::{coordinate, work};
+use astar::astar::sma;
+use astar::utils::analysis::{analyze, print_table, write_json};
+use astar::utils::bfs::enumerate;
+use astar::utils::check::{check_exact, exact_distances, grid_samples, print_report};
+use astar::utils::combinators::Max;
+use astar::utils::filter::{default_buckets, filter_tiers};
+use astar::utils::helpers::{man_dist, misplaced, read_tests, setup_tests, weighted_man_dist};
+use astar::utils::keys::{grid_report, print_report as print_keys, reachable};
+use astar::utils::map::{octile, read_scen, scen_states, GridMap};
+use astar::utils::net::Addr;
+use astar::utils::pdb::{additive, default_pdbs};
+use astar::utils::structs::{AZHasher, KeyWidth, Keys, Limit, ZHasher, Z_SEED};
+use astar::{Algorithm, Error, Grid, Heuristic, ManDist, Result, Solver};
 
-use astar::{dpa, hda, seq};
-use utils::helpers::{gen_tests, man_dist, read_tests};
-use utils::structs::{AZHasher, Grid, Node, StateHash, ZHasher};
+const USAGE: &str = "usage:
+    astar setup
+    astar filter [threads]
+    astar run <tier> <seq|dpa|hda|zhda|external|frontier|sma|rbfs|ida> [threads] [temp]
+    astar analyze <tier> <dpa|hda|zhda> <threads> [temp] [out.json]
+    astar trace <tier> <dpa|hda|zhda> <threads> [temp]
+    astar pdb <tier> <seq|dpa|hda|zhda|external|frontier|sma|rbfs|ida> [threads] [temp]
+    astar map <file.scen> <seq|dpa|hda|zhda|external|frontier|sma|rbfs|ida> [threads] [temp]
+    astar bfs <rows> <cols> [threads] [samples]
+    astar check <rows> <cols> <man_dist|misplaced|weighted_man_dist> [samples] [walk]
+    astar keys <rows> <cols> [states] [threads] [seed]
+    astar coordinator <tier> <workers> [tcp|unix] [heuristic] [32|64|128] [seed]
+    astar worker <tcp:ip:port|unix:path> <tcp:ip:port|unix:path> [heuristic]";
 
-pub fn setup() {
-    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    let test_files = vec!["easy", "medium", "hard"];
-    let params = [(4, 30, 25, 30), (4, 30, 30, 35), (5, 30, 30, 35)];
+// Pattern databases are cached here.
+const PDB_DIR: &str = "pdb";
 
-    for i in 0..3 {
-        let mut path = root.clone();
-        path.push(format!("src/tests/{}.txt", test_files[i]));
-        let mut f = File::create(path).expect("failed to create file.");
-        let tests = gen_tests(params[i]);
-        f.write_all(tests.as_bytes()).unwrap();
-    }
-}
+// Puzzles with more states are not enumerated by check.
+const MAX_CHECK_STATES: usize = 2_000_000;
 
-pub fn test_dpa(tier: &str, num_threads: usize, temp: f32) {
-    println!("dpa {} t{} {}", tier, num_threads, temp);
-    let test_cases = read_tests(tier);
-    let start = Instant::now();
+pub fn run_tests(tier: &str, solver: &Solver) -> Result<()> {
+    println!(
+        "{} {} t{}",
+        solver.algorithm().name(),
+        tier,
+        solver.num_threads()
+    );
+    let test_cases = read_tests(tier)?;
     let mut tot_dur = Duration::new(0, 0);
     for (i, (s, e)) in test_cases.iter().enumerate() {
-        let loop_start = start.elapsed();
-        let end = dpa::astar(s, e, man_dist, num_threads, temp);
-        let loop_end = start.elapsed();
-        let dur = loop_end - loop_start;
-        println!("- test {} {}: {:?}", i, end.unwrap().f, dur);
-        tot_dur += dur;
+        match solver.solve(s, e) {
+            Ok(sol) => {
+                println!("- test {} {}: {:?}", i, sol.cost, sol.elapsed);
+                tot_dur += sol.elapsed;
+            }
+            Err(err) => println!("- test {}: {}", i, err),
+        }
     }
     println!("Total time: {:?}", tot_dur);
-    println!("Average time: {:?}", tot_dur / test_cases.len() as u32);
+    println!(
+        "Average time: {:?}",
+        tot_dur / test_cases.len().max(1) as u32
+    );
+    Ok(())
 }
 
-pub fn test_hda(tier: &str, num_threads: usize) {
-    println!("hda {} t{}", tier, num_threads);
-    let test_cases = read_tests(tier);
-    let start = Instant::now();
-    let mut tot_dur = Duration::new(0, 0);
-    for (i, (s, e)) in test_cases.iter().enumerate() {
-        let hasher = ZHasher::new(s.size);
-        let loop_start = start.elapsed();
-        let end = hda::astar(s, e, man_dist, num_threads, hasher);
-        let loop_end = start.elapsed();
-        let dur = loop_end - loop_start;
-        println!("- test {} {}: {:?}", i, end.unwrap().f, dur);
-        tot_dur += dur;
+pub fn analyze_tests(
+    tier: &str,
+    algorithm: Algorithm,
+    num_threads: usize,
+    out: Option<&str>,
+) -> Result<()> {
+    let reports = read_tests(tier)?
+        .iter()
+        .enumerate()
+        .map(|(i, (s, e))| {
+            let id = format!("{}.{}", tier, i);
+            analyze(&id, s, e, weighted_man_dist, algorithm, num_threads)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    print_table(&reports);
+    if let Some(path) = out {
+        write_json(path, &reports)?;
     }
-    println!("Total time: {:?}", tot_dur);
-    println!("Average time: {:?}", tot_dur / test_cases.len() as u32);
+    Ok(())
 }
 
-pub fn test_zhda(tier: &str, num_threads: usize) {
-    println!("abs hda {} t{}", tier, num_threads);
-    let test_cases = read_tests(tier);
-    let start = Instant::now();
-    let mut tot_dur = Duration::new(0, 0);
-    for (i, (s, e)) in test_cases.iter().enumerate() {
-        let hasher = AZHasher::new(s.size);
-        let loop_start = start.elapsed();
-        let end = hda::astar(s, e, man_dist, num_threads, hasher);
-        let loop_end = start.elapsed();
-        let dur = loop_end - loop_start;
-        println!("- test {} {}: {:?}", i, end.unwrap().f, dur);
-        tot_dur += dur;
+// run_tests with additive pattern databases of the tier's goal, built
+// with the solver's threads the first time.
+pub fn run_pdb_tests(tier: &str, algorithm: Algorithm, num_threads: usize) -> Result<()> {
+    let test_cases = read_tests(tier)?;
+    let goal = match test_cases.first() {
+        Some((_, e)) => e,
+        None => {
+            println!("{}: no tests", tier);
+            return Ok(());
+        }
+    };
+    let pdbs = default_pdbs(PDB_DIR, goal, num_threads)?;
+    for pdb in &pdbs {
+        println!("{:?}", pdb);
     }
-    println!("Total time: {:?}", tot_dur);
-    println!("Average time: {:?}", tot_dur / test_cases.len() as u32);
+    let h = Max::new().with(weighted_man_dist).with(additive(pdbs));
+    let solver = Solver::new(algorithm).threads(num_threads).heuristic(h);
+    run_tests(tier, &solver)
 }
 
-pub fn test_seq(tier: &str) {
-    println!("seq {}", tier);
-    let test_cases = read_tests(tier);
-    let start = Instant::now();
+// Trace a search on the first test of a tier.
+// Open the written file in chrome://tracing or ui.perfetto.dev.
+pub fn trace_tests(tier: &str, algorithm: Algorithm, num_threads: usize) -> Result<()> {
+    let (s, e) = match read_tests(tier)?.into_iter().next() {
+        Some(test) => test,
+        None => {
+            println!("{}: no tests", tier);
+            return Ok(());
+        }
+    };
+    let solver = Solver::new(algorithm).threads(num_threads).tracing(true);
+    let sol = solver.solve(&s, &e)?;
+    let path = format!("{}_{}_t{}.trace.json", algorithm.name(), tier, num_threads);
+    sol.trace.write_chrome(&path)?;
+    println!("{}: {} events", path, sol.trace.events.len());
+    Ok(())
+}
+
+// Solve the scenarios of a Moving AI .scen file, the maps are looked up
+// next to it, first by the path given in the file, then by file name.
+pub fn run_scen(path: &str, algorithm: Algorithm, num_threads: usize) -> Result<()> {
+    let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+    let mut maps: Vec<(String, Arc<GridMap>)> = vec![];
+    println!("{} {} t{}", algorithm.name(), path, num_threads);
+    for (i, scen) in read_scen(path)?.iter().enumerate() {
+        let map = match maps.iter().find(|(name, _)| *name == scen.map) {
+            Some((_, map)) => map.clone(),
+            None => {
+                let mut file = dir.join(&scen.map);
+                if !file.exists() {
+                    file = dir.join(Path::new(&scen.map).file_name().unwrap_or_default());
+                }
+                let map = Arc::new(GridMap::load(file)?);
+                maps.push((scen.map.clone(), map.clone()));
+                map
+            }
+        };
+        let (s, e) = scen_states(&map, scen);
+        let solver = Solver::with_heuristic(algorithm, octile).threads(num_threads);
+        match solver.solve(&s, &e) {
+            Ok(sol) => {
+                println!(
+                    "- scen {} {:.4} (optimal {:.4}): {:?}",
+                    i, sol.cost.0, scen.optimal, sol.elapsed
+                );
+            }
+            Err(err) => println!("- scen {}: {}", i, err),
+        }
+    }
+    Ok(())
+}
+
+// run_tests on worker processes of this binary, started for every
+// test, that send each other nodes over tcp or unix sockets.
+// Every worker listens for its peers on an address of its own.
+pub fn run_distributed(
+    tier: &str,
+    num_workers: usize,
+    transport: &str,
+    h: &str,
+    keys: Keys,
+) -> Result<()> {
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    let listen = |name: &str| match transport {
+        #[cfg(unix)]
+        "unix" => {
+            let name = format!("astar-{}-{}.sock", std::process::id(), name);
+            Addr::Unix(std::env::temp_dir().join(name))
+        }
+        _ => Addr::Tcp(([127, 0, 0, 1], 0).into()),
+    };
+    let addr = listen("coordinator");
+    let exe = std::env::current_exe()?;
+    let mut spawned = 0;
+    let mut spawn = |addr: &Addr| {
+        spawned += 1;
+        Command::new(&exe)
+            .args([
+                "worker",
+                &addr.to_string(),
+                &listen(&spawned.to_string()).to_string(),
+                h,
+            ])
+            .spawn()
+    };
+    println!("distributed {} p{} {}", tier, num_workers, transport);
+    let test_cases = read_tests(tier)?;
     let mut tot_dur = Duration::new(0, 0);
     for (i, (s, e)) in test_cases.iter().enumerate() {
-        let loop_start = start.elapsed();
-        seq::astar(s, e, man_dist);
-        let loop_end = start.elapsed();
-        let dur = loop_end - loop_start;
-        println!("- test {}: {:?}", i, dur);
-        tot_dur += dur;
+        let start = Instant::now();
+        let limit = Limit::none();
+        match coordinate(s, e, num_workers, &addr, &mut spawn, keys, &limit) {
+            Ok((Some(end), logs)) => {
+                let elapsed = start.elapsed();
+                let sent: i32 = logs.iter().map(|l| l.node_cnt).sum();
+                println!("- test {} {}: {:?}, {} nodes sent", i, end.g, elapsed, sent);
+                tot_dur += elapsed;
+            }
+            Ok((None, _)) if limit.reached() => println!("- test {}: {}", i, Error::LimitReached),
+            Ok((None, _)) => println!("- test {}: {}", i, Error::NoSolution),
+            Err(err) => println!("- test {}: {}", i, err),
+        }
     }
     println!("Total time: {:?}", tot_dur);
-    println!("Average time: {:?}", tot_dur / test_cases.len() as u32);
+    println!(
+        "Average time: {:?}",
+        tot_dur / test_cases.len().max(1) as u32
+    );
+    Ok(())
 }
 
-pub fn filter_tests() -> () {
-    let tiers = ["easy", "medium", "hard"];
-    let mut res = vec![];
-    for tier in tiers {
-        let test_cases = read_tests(tier);
-        let start = Instant::now();
-        for (i, (s, e)) in test_cases.iter().enumerate() {
-            let loop_start = start.elapsed();
-            seq::astar(s, e, man_dist);
-            let loop_end = start.elapsed();
-            let dur = loop_end - loop_start;
-            // println!("test {}: {:?}", i, dur);
-            if dur > Duration::new(1, 0) {
-                println!("add {}.{}", tier, i);
-                res.push((s.clone(), e.clone()));
+// Check a heuristic against exact distances on a small puzzle,
+// with samples walk random moves away from the goal.
+pub fn check_heuristic(
+    goal: &Grid,
+    name: &str,
+    h: &dyn Heuristic<Grid>,
+    samples: usize,
+    walk: i32,
+) -> Result<()> {
+    let dist = exact_distances(goal, MAX_CHECK_STATES)?;
+    println!(
+        "{}x{}: {} states reach the goal",
+        goal.rows,
+        goal.cols,
+        dist.len()
+    );
+    let samples = grid_samples(goal, samples, walk, 1);
+    let report = check_exact(h, goal, &samples, &dist)?;
+    print_report(name, &report);
+    Ok(())
+}
+
+// Compare the keys of the Zobrist hashers on the states nearest to a goal.
+pub fn compare_keys(goal: &Grid, states: usize, num_threads: usize, seed: u64) {
+    let (rows, cols) = (goal.rows, goal.cols);
+    let states = reachable(goal, states);
+    let z32 = ZHasher::<u32>::with_seed(rows, cols, seed);
+    print_keys("zobrist32", &grid_report(&states, &z32, num_threads));
+    let z64 = ZHasher::<u64>::with_seed(rows, cols, seed);
+    print_keys("zobrist64", &grid_report(&states, &z64, num_threads));
+    let z128 = ZHasher::<u128>::with_seed(rows, cols, seed);
+    print_keys("zobrist128", &grid_report(&states, &z128, num_threads));
+    let az32 = AZHasher::<u32>::with_seed(rows, cols, seed);
+    print_keys("abstract32", &grid_report(&states, &az32, num_threads));
+    let az64 = AZHasher::<u64>::with_seed(rows, cols, seed);
+    print_keys("abstract64", &grid_report(&states, &az64, num_threads));
+}
+
+// Enumerate every board reachable from a goal and check that the
+// solvers find optimal costs on samples walk moves away from it.
+pub fn enumerate_boards(goal: &Grid, num_threads: usize, samples: usize) -> Result<()> {
+    let space = enumerate(goal, num_threads)?;
+    println!(
+        "{}x{}: {} boards, radius {}",
+        goal.rows,
+        goal.cols,
+        space.reachable(),
+        space.radius()
+    );
+    for (d, n) in space.layers().iter().enumerate() {
+        println!("- {}: {}", d, n);
+    }
+    let starts = grid_samples(goal, samples, 200, 1);
+    for algorithm in [
+        Algorithm::Seq,
+        Algorithm::Dpa { temp: 0.6 },
+        Algorithm::Hda,
+        Algorithm::Zhda,
+    ] {
+        let solver = Solver::new(algorithm).threads(num_threads);
+        let mut optimal = 0;
+        for s in &starts {
+            if Some(solver.solve(s, goal)?.cost) == space.distance(s) {
+                optimal += 1;
             }
         }
+        println!("{}: {}/{} optimal", algorithm.name(), optimal, starts.len());
+    }
+    Ok(())
+}
+
+fn parse_heuristic(name: &str, goal: &Grid) -> Option<Box<dyn Heuristic<Grid>>> {
+    match name {
+        "man_dist" => Some(Box::new(man_dist)),
+        "weighted_man_dist_table" => Some(Box::new(ManDist::new(goal))),
+        "misplaced" => Some(Box::new(misplaced)),
+        "weighted_man_dist" => Some(Box::new(weighted_man_dist)),
+        _ => None,
     }
-    let tests = serde_json::to_string(&res).unwrap();
-    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    path.push("src/tests/oneplus.txt");
-    let mut f = File::create(path).expect("failed to create file.");
-    f.write_all(tests.as_bytes()).unwrap();
+}
+
+fn parse_algorithm(name: &str, temp: f32) -> Option<Algorithm> {
+    match name {
+        "seq" => Some(Algorithm::Seq),
+        "dpa" => Some(Algorithm::Dpa { temp }),
+        "hda" => Some(Algorithm::Hda),
+        "zhda" => Some(Algorithm::Zhda),
+        "external" => Some(Algorithm::External),
+        "frontier" => Some(Algorithm::Frontier),
+        "sma" => Some(Algorithm::Sma {
+            max_nodes: sma::DEFAULT_NODES,
+        }),
+        "rbfs" => Some(Algorithm::Rbfs),
+        "ida" => Some(Algorithm::Ida),
+        _ => None,
+    }
+}
+
+// None on bad arguments, Some(Err) if the command failed.
+fn cli(args: &[String]) -> Option<Result<()>> {
+    let arg = |i: usize| args.get(i).map(|s| s.as_str());
+    let num = |i: usize, default: usize| arg(i).map_or(Some(default), |s| s.parse().ok());
+    let temp = |i: usize| arg(i).map_or(Some(0.6), |s| s.parse().ok());
+
+    let res = match arg(0)? {
+        "setup" => setup_tests(),
+        "filter" => filter_tiers(&["easy", "medium", "hard"], &default_buckets(), num(1, 8)?),
+        "run" => {
+            let algorithm = parse_algorithm(arg(2)?, temp(4)?)?;
+            let solver = Solver::new(algorithm).threads(num(3, 8)?);
+            run_tests(arg(1)?, &solver)
+        }
+        "analyze" => {
+            let algorithm = parse_algorithm(arg(2)?, temp(4)?)?;
+            analyze_tests(arg(1)?, algorithm, num(3, 8)?, arg(5))
+        }
+        "pdb" => {
+            let algorithm = parse_algorithm(arg(2)?, temp(4)?)?;
+            run_pdb_tests(arg(1)?, algorithm, num(3, 8)?)
+        }
+        "trace" => {
+            let algorithm = parse_algorithm(arg(2)?, temp(4)?)?;
+            trace_tests(arg(1)?, algorithm, num(3, 8)?)
+        }
+        "map" => {
+            let algorithm = parse_algorithm(arg(2)?, temp(4)?)?;
+            run_scen(arg(1)?, algorithm, num(3, 1)?)
+        }
+        "bfs" => {
+            let (rows, cols): (i32, i32) = (arg(1)?.parse().ok()?, arg(2)?.parse().ok()?);
+            if rows < 1 || cols < 1 {
+                return None;
+            }
+            let goal = Grid::rand_rect_with_seed(rows, cols, 0);
+            enumerate_boards(&goal, num(3, 8)?, num(4, 20)?)
+        }
+        "check" => {
+            let (rows, cols): (i32, i32) = (arg(1)?.parse().ok()?, arg(2)?.parse().ok()?);
+            if rows < 1 || cols < 1 {
+                return None;
+            }
+            let goal = Grid::rand_rect_with_seed(rows, cols, 0);
+            let h = parse_heuristic(arg(3)?, &goal)?;
+            check_heuristic(&goal, arg(3)?, &*h, num(4, 1000)?, num(5, 50)? as i32)
+        }
+        "keys" => {
+            let (rows, cols): (i32, i32) = (arg(1)?.parse().ok()?, arg(2)?.parse().ok()?);
+            if rows < 1 || cols < 1 {
+                return None;
+            }
+            let goal = Grid::rand_rect_with_seed(rows, cols, 0);
+            compare_keys(
+                &goal,
+                num(3, 1_000_000)?,
+                num(4, 8)?,
+                num(5, Z_SEED as usize)? as u64,
+            );
+            Ok(())
+        }
+        "coordinator" => {
+            let transport = arg(3).unwrap_or(if cfg!(unix) { "unix" } else { "tcp" });
+            let h = arg(4).unwrap_or("weighted_man_dist");
+            // Any goal tells whether the workers will know the heuristic.
+            let sample = Grid::rand_rect_with_seed(2, 2, 0);
+            let unix = cfg!(unix) && transport == "unix";
+            if !(transport == "tcp" || unix) || parse_heuristic(h, &sample).is_none() {
+                return None;
+            }
+            let keys = Keys {
+                width: KeyWidth::from_bits(num(5, 32)? as u32)?,
+                seed: match arg(6) {
+                    Some(seed) => Some(seed.parse().ok()?),
+                    None => None,
+                },
+            };
+            run_distributed(arg(1)?, num(2, 4)?, transport, h, keys)
+        }
+        "worker" => {
+            let addr: Addr = arg(1)?.parse().ok()?;
+            let listen: Addr = arg(2)?.parse().ok()?;
+            let h = arg(3).unwrap_or("weighted_man_dist");
+            work(&addr, &listen, |goal| {
+                parse_heuristic(h, goal).ok_or_else(|| Error::Parse(format!("heuristic {}", h)))
+            })
+        }
+        _ => return None,
+    };
+    Some(res)
 }
 
 fn main() {
-    // println!("Hello, world!");
-    // let end_state = Grid::rand_with_seed(6, 13, 69);
-    // println!("end:\n{}", end_state);
-
-    // let start_state = end_state.rand_actions(200);
-    // println!(
-    //     "dist from end state: {d}",
-    //     d = man_dist(&start_state, &end_state)
-    // );
-    // println!("start:\n{}", start_state);
-
-    // println!("seq:");
-    // let start = Instant::now();
-    // let n = seq::astar(&start_state, &end_state, man_dist).unwrap();
-    // println!("{}", n.state);
-    // println!("f: {}, g: {}, h: {}", n.f, n.g, n.h);
-    // let dur = start.elapsed();
-    // println!("time: {:?}", dur);
-
-    // println!("dpa with temp 0.8:");
-    // let start = Instant::now();
-    // let n = dpa::astar(&start_state, &end_state, man_dist, 8, 0.8).unwrap();
-    // println!("{}", n.state);
-    // println!("f: {}, g: {}, h: {}", n.f, n.g, n.h);
-    // let dur = start.elapsed();
-    // println!("time: {:?}", dur);
-
-    // println!("dpa with temp 0.4:");
-    // let start = Instant::now();
-    // let n = dpa::astar(&start_state, &end_state, man_dist, 8, 0.4).unwrap();
-    // println!("{}", n.state);
-    // println!("f: {}, g: {}, h: {}", n.f, n.g, n.h);
-    // let dur = start.elapsed();
-    // println!("time: {:?}", dur);
-
-    // println!("hda with zobrist:");
-    // let start = Instant::now();
-    // let hasher = ZHasher::new(start_state.size);
-    // let n = hda::astar(&start_state, &end_state, man_dist, 8, hasher).unwrap();
-    // println!("{}", n.state);
-    // println!("f: {}, g: {}, h: {}", n.f, n.g, n.h);
-    // let dur = start.elapsed();
-    // println!("time: {:?}", dur);
-
-    // let hasher = ZHasher::new(5);
-    // let states: Vec<Grid> = (0..100).map(|i| end_state.rand_actions(i)).collect();
-    // let start = Instant::now();
-    // states.iter().map(|s| s.hash_with(&hasher)).for_each(drop);
-    // // .for_each(|v| println!("hash value: {:0>32b}", v));
-    // let dur = start.elapsed();
-    // println!("time: {:?}", dur);
-
-    // setup();
-    // filter_tests();
-
-    // let tests = read_tests("hard");
-    // for (i, test) in tests.iter().enumerate() {
-    //     let (start, end) = test;
-    //     // println!("start:\n{}", start);
-    //     // println!("end:\n{}", end);
-    //     println!("test case {}", i);
-    //     println!("man dist: {}\n", man_dist(start, end));
-
-    //     // if i > 10 {
-    //     //     break;
-    //     // }
-    // }
-
-    // test_seq("oneplus");
-
-    // test_dpa("oneplus", 2, 0.6);
-    // test_dpa("oneplus", 4, 0.6);
-    test_dpa("oneplus", 8, 0.6);
-    // test_dpa("oneplus", 16, 0.6);
-
-    // test_hda("oneplus", 2);
-    // test_hda("oneplus", 4);
-    test_hda("oneplus", 8);
-    // test_hda("oneplus", 16);
-
-    // test_zhda("oneplus", 2);
-    // test_zhda("oneplus", 4);
-    test_zhda("oneplus", 8);
-    // test_zhda("oneplus", 16);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() {
+        // The default benchmark run
+        for algorithm in [
+            Algorithm::Dpa { temp: 0.6 },
+            Algorithm::Hda,
+            Algorithm::Zhda,
+        ] {
+            if let Err(err) = run_tests("oneplus", &Solver::new(algorithm).threads(8)) {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    match cli(&args) {
+        None => println!("{}", USAGE),
+        Some(Err(err)) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+        Some(Ok(())) => {}
+    }
 }
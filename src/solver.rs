@@ -0,0 +1,436 @@
+//! High level entry point to all searches.
+//!
+//! ```no_run
+//! use astar::{weighted_man_dist, Algorithm, Grid, Solver};
+//!
+//! let end = Grid::rand_with_seed(4, 69);
+//! let start = end.rand_actions_with_seed(60, 7);
+//! let solution = Solver::new(Algorithm::Hda)
+//!     .threads(4)
+//!     .heuristic(weighted_man_dist)
+//!     .max_expansions(1_000_000)
+//!     .solve(&start, &end)
+//!     .unwrap();
+//! println!("cost: {}", solution.cost);
+//! ```
+use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::astar::dpa::{RandRouter, ROUND_LEN};
+use crate::astar::hda::HashRouter;
+use crate::astar::{dpa, external, frontier, hda, ida, lockstep, rbfs, seq, sma};
+use crate::error::{Error, Result};
+use crate::utils::helpers::{derive_seed, weighted_man_dist};
+use crate::utils::heuristic::Heuristic;
+use crate::utils::structs::{Grid, KeyWidth, Keys, Limit, Log, Node, State, StateHasher};
+use crate::utils::trace::Trace;
+
+/// The search algorithm run by a [`Solver`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    /// Sequential A*.
+    Seq,
+    /// Decentralized parallel A*, successors are sent to a random
+    /// thread with probability `temp`.
+    Dpa { temp: f32 },
+    /// Hash distributed A* with Zobrist hashing.
+    Hda,
+    /// Hash distributed A* with abstract Zobrist hashing,
+    /// which keeps the successors of a state on fewer threads.
+    Zhda,
+    /// External memory A*, keeps its states in files in the
+    /// solver's temp directory.
+    External,
+    /// Frontier A*, keeps no closed list.
+    Frontier,
+    /// Simplified memory-bounded A*, keeps at most `max_nodes` nodes.
+    Sma { max_nodes: usize },
+    /// Recursive best-first search, memory linear in the solution depth.
+    Rbfs,
+    /// Parallel IDA* with a shared transposition table.
+    Ida,
+}
+
+impl Algorithm {
+    pub fn name(&self) -> String {
+        match self {
+            Algorithm::Seq => "seq".to_string(),
+            Algorithm::Dpa { temp } => format!("dpa_p{}", temp),
+            Algorithm::Hda => "hda".to_string(),
+            Algorithm::Zhda => "zhda".to_string(),
+            Algorithm::External => "external".to_string(),
+            Algorithm::Frontier => "frontier".to_string(),
+            Algorithm::Sma { max_nodes } => format!("sma_n{}", max_nodes),
+            Algorithm::Rbfs => "rbfs".to_string(),
+            Algorithm::Ida => "ida".to_string(),
+        }
+    }
+
+    /// True for the searches that run in lock-step in deterministic
+    /// mode and can be traced.
+    pub fn parallel(&self) -> bool {
+        matches!(
+            self,
+            Algorithm::Dpa { .. } | Algorithm::Hda | Algorithm::Zhda
+        )
+    }
+
+    /// Fails for parameters the search cannot run with,
+    /// `temp` must be in `(0, 1]`.
+    pub fn check(&self) -> Result<()> {
+        match *self {
+            Algorithm::Dpa { temp } if !(0.0 < temp && temp <= 1.0) => Err(Error::BadOption(
+                format!("dpa temp {} is not in (0, 1]", temp),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A solved instance.
+#[derive(Debug, Clone)]
+pub struct Solution<S: State = Grid> {
+    /// Cost of the path found.
+    pub cost: S::Cost,
+    /// The goal node the search ended with.
+    pub node: Node<S>,
+    /// One log per search thread.
+    pub logs: Vec<Log>,
+    /// Events of all threads, empty unless tracing is enabled.
+    pub trace: Trace,
+    pub elapsed: Duration,
+}
+
+impl<S: State> Solution<S> {
+    /// Counters summed over all threads.
+    pub fn log(&self) -> Log {
+        let mut total = Log::new();
+        for log in self.logs.iter() {
+            total.merge(log.clone());
+        }
+        total
+    }
+}
+
+/// Configures and runs a search, see the module documentation.
+/// Searches sliding tile puzzles unless built for another [`State`]
+/// with [`Solver::with_heuristic`].
+#[derive(Clone)]
+pub struct Solver<S: State = Grid> {
+    algorithm: Algorithm,
+    heuristic: Arc<dyn Heuristic<S>>,
+    num_threads: usize,
+    max_expansions: Option<u64>,
+    timeout: Option<Duration>,
+    seed: Option<u64>,
+    keys: Keys,
+    tracing: bool,
+    temp_dir: PathBuf,
+}
+
+impl<S: State> Debug for Solver<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Solver")
+            .field("algorithm", &self.algorithm)
+            .field("num_threads", &self.num_threads)
+            .field("max_expansions", &self.max_expansions)
+            .field("timeout", &self.timeout)
+            .field("seed", &self.seed)
+            .field("keys", &self.keys)
+            .field("tracing", &self.tracing)
+            .field("temp_dir", &self.temp_dir)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Solver {
+    /// A solver using `weighted_man_dist` on one thread, without limits.
+    /// It is admissible, unlike `man_dist`, which also counts the blank
+    /// and can overestimate.
+    pub fn new(algorithm: Algorithm) -> Self {
+        Solver::with_heuristic(algorithm, weighted_man_dist)
+    }
+}
+
+impl<S: State> Solver<S> {
+    /// A solver for any domain on one thread, without limits.
+    pub fn with_heuristic<H: Heuristic<S> + 'static>(algorithm: Algorithm, heuristic: H) -> Self {
+        Solver {
+            algorithm,
+            heuristic: Arc::new(heuristic),
+            num_threads: 1,
+            max_expansions: None,
+            timeout: None,
+            seed: None,
+            keys: Keys::default(),
+            tracing: false,
+            temp_dir: std::env::temp_dir(),
+        }
+    }
+
+    /// Either a function like `weighted_man_dist` or a heuristic built
+    /// for the goal like [`ManDist`](crate::utils::heuristic::ManDist).
+    pub fn heuristic<H: Heuristic<S> + 'static>(mut self, heuristic: H) -> Self {
+        self.heuristic = Arc::new(heuristic);
+        self
+    }
+
+    /// Share a heuristic with other solvers, e.g. a large pattern database.
+    pub fn shared_heuristic(mut self, heuristic: Arc<dyn Heuristic<S>>) -> Self {
+        self.heuristic = heuristic;
+        self
+    }
+
+    /// Number of search threads, ignored by `Algorithm::Seq`.
+    pub fn threads(mut self, num_threads: usize) -> Self {
+        self.num_threads = num_threads.max(1);
+        self
+    }
+
+    /// Give up after this many expansions, summed over all threads.
+    pub fn max_expansions(mut self, max_expansions: u64) -> Self {
+        self.max_expansions = Some(max_expansions);
+        self
+    }
+
+    /// Give up after this much wall time.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Run the parallel algorithms in lock-step rounds with thread seeds
+    /// derived from `seed`, which makes runs reproducible.
+    pub fn deterministic(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Width of the Zobrist keys of the hash based searches, `Hda`,
+    /// `Zhda` and `Ida`. 32 bits by default, wider keys collide less
+    /// in the tables of large searches.
+    pub fn key_width(mut self, width: KeyWidth) -> Self {
+        self.keys.width = width;
+        self
+    }
+
+    /// Seed of the Zobrist keys, which also decides which thread owns
+    /// a state. Fixed seeds are used by default.
+    pub fn key_seed(mut self, seed: u64) -> Self {
+        self.keys.seed = Some(seed);
+        self
+    }
+
+    /// Record the events of the parallel threads, see `utils::trace`.
+    /// Not supported in deterministic mode, [`Solver::solve`] fails
+    /// with [`Error::BadOption`] if both are set for `Dpa`, `Hda` or `Zhda`.
+    pub fn tracing(mut self, tracing: bool) -> Self {
+        self.tracing = tracing;
+        self
+    }
+
+    /// Where `Algorithm::External` writes its files,
+    /// the system temp directory by default.
+    pub fn temp_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.temp_dir = dir.into();
+        self
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    /// Fails without searching if start and end do not belong
+    /// together, see [`State::check_pair`], or if the algorithm's
+    /// parameters are out of range, see [`Algorithm::check`], or if
+    /// tracing is asked for in deterministic mode.
+    pub fn solve(&self, start: &S, end: &S) -> Result<Solution<S>> {
+        self.algorithm.check()?;
+        if self.tracing && self.seed.is_some() && self.algorithm.parallel() {
+            return Err(Error::BadOption(
+                "tracing is not supported in deterministic mode".to_string(),
+            ));
+        }
+        start.check_pair(end)?;
+
+        let h = self.heuristic.clone();
+        let n = self.num_threads;
+        let limit = Arc::new(Limit::new(self.max_expansions, self.timeout));
+        let t = Instant::now();
+        let (node, logs, trace) = match (self.algorithm, self.seed) {
+            (Algorithm::Seq, _) => {
+                let (node, log) = seq::solve(start, end, &*h, &limit);
+                (node, vec![log], Trace::new())
+            }
+            (Algorithm::Frontier, _) => {
+                let (node, log) = frontier::solve(start, end, &*h, &limit);
+                (node, vec![log], Trace::new())
+            }
+            (Algorithm::Sma { max_nodes }, _) => {
+                let (node, log) = sma::solve(start, end, &*h, max_nodes, &limit);
+                (node, vec![log], Trace::new())
+            }
+            (Algorithm::Rbfs, _) => {
+                let (node, log) = rbfs::solve(start, end, &*h, &limit);
+                (node, vec![log], Trace::new())
+            }
+            (Algorithm::Ida, _) => {
+                let hasher = start.keyed_hasher(self.keys);
+                let (node, logs) = ida::solve(start, end, &*h, n, hasher, &limit);
+                (node, logs, Trace::new())
+            }
+            (Algorithm::External, _) => {
+                let (node, log) = external::solve(start, end, &*h, n, &self.temp_dir, &limit)?;
+                (node, vec![log], Trace::new())
+            }
+            (Algorithm::Dpa { temp }, None) => {
+                dpa::solve(start, end, h, n, temp, limit.clone(), self.tracing)
+            }
+            (Algorithm::Hda, None) => {
+                let hasher = start.keyed_hasher(self.keys);
+                hda::solve(start, end, h, n, hasher, limit.clone(), self.tracing)
+            }
+            (Algorithm::Zhda, None) => {
+                let hasher = start.keyed_hasher(self.keys);
+                let abstract_hasher = start.keyed_abstract_hasher(self.keys);
+                let tracing = self.tracing;
+                hda::solve_abstract(
+                    start,
+                    end,
+                    h,
+                    n,
+                    hasher,
+                    abstract_hasher,
+                    limit.clone(),
+                    tracing,
+                )
+            }
+            (Algorithm::Dpa { temp }, Some(seed)) => {
+                let routers = (0..n)
+                    .map(|i| RandRouter::new(derive_seed(seed, i as u64), temp, i, n))
+                    .collect();
+                let (node, logs) =
+                    lockstep::astar(start, end, h, routers, ROUND_LEN, limit.clone());
+                (node, logs, Trace::new())
+            }
+            (Algorithm::Hda, Some(_)) => {
+                let hasher = start.keyed_hasher(self.keys);
+                hash_lockstep(start, end, h, n, hasher, limit.clone())
+            }
+            (Algorithm::Zhda, Some(_)) => {
+                let hasher = start.keyed_abstract_hasher(self.keys);
+                hash_lockstep(start, end, h, n, hasher, limit.clone())
+            }
+        };
+        let elapsed = t.elapsed();
+
+        match node {
+            Some(node) => Ok(Solution {
+                cost: node.g,
+                node,
+                logs,
+                trace,
+                elapsed,
+            }),
+            None if limit.reached() => Err(Error::LimitReached),
+            None => Err(Error::NoSolution),
+        }
+    }
+}
+
+fn hash_lockstep<S: State, T: StateHasher<S>>(
+    start: &S,
+    end: &S,
+    h: Arc<dyn Heuristic<S>>,
+    n: usize,
+    hasher: T,
+    limit: Arc<Limit>,
+) -> (Option<Node<S>>, Vec<Log>, Trace) {
+    let routers = (0..n).map(|_| HashRouter::new(hasher.clone(), n)).collect();
+    let (node, logs) = lockstep::astar(start, end, h, routers, ROUND_LEN, limit);
+    (node, logs, Trace::new())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::utils::bfs::enumerate;
+    use crate::utils::structs::TileCost;
+
+    fn algorithms() -> Vec<Algorithm> {
+        vec![
+            Algorithm::Seq,
+            Algorithm::Dpa { temp: 0.5 },
+            Algorithm::Hda,
+            Algorithm::Zhda,
+            Algorithm::External,
+            Algorithm::Frontier,
+            Algorithm::Sma { max_nodes: 5000 },
+            Algorithm::Rbfs,
+            Algorithm::Ida,
+        ]
+    }
+
+    // Every algorithm, free running and in lock-step, must find the
+    // distance of the breadth first enumeration.
+    fn check_optimal(rows: i32, cols: i32) {
+        let end = Grid::rand_rect_with_seed(rows, cols, 0);
+        let space = enumerate(&end, 2).unwrap();
+        for seed in 0..4 {
+            let start = end.rand_actions_with_seed(40, seed);
+            let distance = space.distance(&start).unwrap();
+            for algorithm in algorithms() {
+                let solver = Solver::new(algorithm).threads(3);
+                let mut solvers = vec![solver.clone()];
+                if algorithm.parallel() {
+                    solvers.push(solver.deterministic(seed));
+                }
+                for solver in solvers {
+                    let solution = solver.solve(&start, &end).unwrap();
+                    assert_eq!(solution.cost, distance, "{:?} from {}", solver, start);
+                }
+            }
+        }
+    }
+
+    // Unit and heavy boards of rows x cols, starts a random walk of len
+    // moves from them, and the cost of the sequential search between the two.
+    pub(crate) fn instances(rows: i32, cols: i32, len: i32) -> Vec<(Grid, Grid, i32)> {
+        let mut instances = vec![];
+        for cost in [TileCost::Unit, TileCost::Heavy] {
+            let end = Grid::rand_rect_with_seed(rows, cols, 69).with_cost(cost);
+            for seed in 0..4 {
+                let start = end.rand_actions_with_seed(len, seed);
+                let best = seq::astar(&start, &end, weighted_man_dist).unwrap().g;
+                instances.push((start, end.clone(), best));
+            }
+        }
+        instances
+    }
+
+    #[test]
+    fn no_tracing_in_lock_step() {
+        let end = Grid::rand_with_seed(3, 0);
+        let start = end.rand_actions_with_seed(10, 0);
+        let solver = Solver::new(Algorithm::Hda).tracing(true);
+        assert!(solver.solve(&start, &end).is_ok());
+        let res = solver.deterministic(1).solve(&start, &end);
+        assert!(matches!(res, Err(Error::BadOption(_))));
+    }
+
+    #[test]
+    fn optimal_on_3x3() {
+        check_optimal(3, 3);
+    }
+
+    #[test]
+    fn optimal_on_2x4() {
+        check_optimal(2, 4);
+    }
+}
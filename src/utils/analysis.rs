@@ -0,0 +1,156 @@
+// Parallel search analysis.
+// Runs seq and a parallel algorithm on the same instances and reports
+// the usual HDA* metrics:
+// - speedup: seq time / parallel time
+// - search overhead: parallel expansions / seq expansions
+// - communication overhead: nodes sent to other threads / nodes generated
+// - load balance: max / mean expansions per thread
+// - cv: coefficient of variation of the expansions per thread
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use crate::error::Result;
+use crate::solver::{Algorithm, Solver};
+use crate::utils::heuristic::Heuristic;
+use crate::utils::structs::{Grid, Log};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    pub id: String,
+    pub algo: String,
+    pub num_threads: usize,
+    pub seq_cost: i32,
+    pub par_cost: i32,
+    pub seq_secs: f64,
+    pub par_secs: f64,
+    pub speedup: f64,
+    pub seq_expanded: i32,
+    pub par_expanded: i32,
+    pub search_overhead: f64,
+    pub comm_overhead: f64,
+    pub load_balance: f64,
+    pub cv: f64,
+}
+
+pub fn analyze<H: Heuristic<Grid> + Clone + 'static>(
+    id: &str,
+    start: &Grid,
+    end: &Grid,
+    h_func: H,
+    algorithm: Algorithm,
+    num_threads: usize,
+) -> Result<Report> {
+    // Invalid input is an error, failed searches are reported with cost -1.
+    start.check_pair(end)?;
+    let seq = Solver::new(Algorithm::Seq)
+        .heuristic(h_func.clone())
+        .solve(start, end);
+    let par = Solver::new(algorithm)
+        .heuristic(h_func)
+        .threads(num_threads)
+        .solve(start, end);
+
+    let (seq_cost, seq_secs, seq_log) = match &seq {
+        Ok(sol) => (sol.cost, sol.elapsed.as_secs_f64(), sol.log()),
+        Err(_) => (-1, 0.0, Log::new()),
+    };
+    let (par_cost, par_secs, logs) = match &par {
+        Ok(sol) => (sol.cost, sol.elapsed.as_secs_f64(), sol.logs.clone()),
+        Err(_) => (-1, 0.0, vec![]),
+    };
+
+    let mut total = Log::new();
+    for log in logs.iter() {
+        total.merge(log.clone());
+    }
+    let (load_balance, cv) = balance(&logs);
+
+    Ok(Report {
+        id: id.to_string(),
+        algo: algorithm.name(),
+        num_threads,
+        seq_cost,
+        par_cost,
+        seq_secs,
+        par_secs,
+        speedup: if par_secs > 0.0 {
+            seq_secs / par_secs
+        } else {
+            0.0
+        },
+        seq_expanded: seq_log.exp_cnt,
+        par_expanded: total.exp_cnt,
+        search_overhead: ratio(total.exp_cnt, seq_log.exp_cnt),
+        comm_overhead: ratio(total.node_cnt, total.gen_cnt),
+        load_balance,
+        cv,
+    })
+}
+
+// (max / mean, stddev / mean) of the expansions per thread
+pub fn balance(logs: &[Log]) -> (f64, f64) {
+    let exps: Vec<f64> = logs.iter().map(|l| l.exp_cnt as f64).collect();
+    if exps.is_empty() {
+        return (1.0, 0.0);
+    }
+    let n = exps.len() as f64;
+    let mean = exps.iter().sum::<f64>() / n;
+    if mean == 0.0 {
+        return (1.0, 0.0);
+    }
+    let max = exps.iter().cloned().fold(0.0, f64::max);
+    let var = exps.iter().map(|e| (e - mean).powi(2)).sum::<f64>() / n;
+    (max / mean, var.sqrt() / mean)
+}
+
+fn ratio(a: i32, b: i32) -> f64 {
+    if b == 0 {
+        return 0.0;
+    }
+    a as f64 / b as f64
+}
+
+pub fn print_table(reports: &[Report]) {
+    println!(
+        "{:<12} {:<10} {:>3} {:>5} {:>5} {:>9} {:>9} {:>8} {:>8} {:>8} {:>6} {:>6}",
+        "id", "algo", "t", "cost", "pcost", "seq(s)", "par(s)", "speedup", "so", "co", "lb", "cv"
+    );
+    for r in reports {
+        println!(
+            "{:<12} {:<10} {:>3} {:>5} {:>5} {:>9.4} {:>9.4} {:>8.2} {:>8.3} {:>8.3} {:>6.2} {:>6.2}",
+            r.id,
+            r.algo,
+            r.num_threads,
+            r.seq_cost,
+            r.par_cost,
+            r.seq_secs,
+            r.par_secs,
+            r.speedup,
+            r.search_overhead,
+            r.comm_overhead,
+            r.load_balance,
+            r.cv
+        );
+    }
+    if reports.is_empty() {
+        return;
+    }
+    let n = reports.len() as f64;
+    let mean = |f: fn(&Report) -> f64| reports.iter().map(f).sum::<f64>() / n;
+    println!(
+        "mean: speedup {:.2}, so {:.3}, co {:.3}, lb {:.2}, cv {:.2}",
+        mean(|r| r.speedup),
+        mean(|r| r.search_overhead),
+        mean(|r| r.comm_overhead),
+        mean(|r| r.load_balance),
+        mean(|r| r.cv)
+    );
+}
+
+pub fn write_json<P: AsRef<Path>>(path: P, reports: &[Report]) -> Result<()> {
+    let mut f = File::create(path)?;
+    f.write_all(serde_json::to_string_pretty(reports)?.as_bytes())?;
+    Ok(())
+}
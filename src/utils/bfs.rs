@@ -0,0 +1,286 @@
+// Breadth first enumeration of every board reachable from a goal, for
+// boards of up to 12 cells with one blank (3x3, 2x4, 2x5, 3x4).
+// Boards are indexed by their Myrvold-Ruskey rank. The search keeps
+// - a 2 bit code per rank: unseen, or the distance from the goal mod 3
+// - a bitmap of the current layer and one of the next layer
+// so 3x4 needs 4 bits for each of its 12! ranks, about 240MB.
+// The threads scan disjoint parts of the current layer and claim unseen
+// boards with a compare and swap on their code.
+// The distance of one board is found by walking towards the goal: a
+// neighbour whose code is one less mod 3 is one move closer, since the
+// distances of neighbours differ by exactly one.
+// Distances count moves, they are the optimal costs for unit tile costs.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+
+use crate::error::{Error, Result};
+use crate::utils::rank::{count_partial, grid_perm, Ranking};
+use crate::utils::structs::Grid;
+
+// Boards of more ranks are not enumerated.
+pub const MAX_RANKS: u64 = 1 << 30;
+const UNSEEN: u64 = 3;
+const RANKING: Ranking = Ranking::MyrvoldRuskey;
+
+#[derive(Debug, Clone)]
+pub struct StateSpace {
+    pub rows: i32,
+    pub cols: i32,
+    goal: u64,
+    // 32 codes per word
+    codes: Vec<u64>,
+    // layers[d] boards are d moves from the goal
+    layers: Vec<u64>,
+}
+
+pub fn enumerate(goal: &Grid, threads: usize) -> Result<StateSpace> {
+    goal.validate()?;
+    let n = goal.data.len();
+    if n > 20 || count_partial(n, n) > MAX_RANKS {
+        return Err(Error::TooLarge(format!(
+            "{}x{} has more than {} boards",
+            goal.rows, goal.cols, MAX_RANKS
+        )));
+    }
+    let start = goal.rank(RANKING)?;
+    let size = count_partial(n, n);
+    let words = size.div_ceil(64) as usize;
+    let codes: Vec<AtomicU64> = (0..size.div_ceil(32))
+        .map(|_| AtomicU64::new(u64::MAX))
+        .collect();
+    let mut cur: Vec<AtomicU64> = (0..words).map(|_| AtomicU64::new(0)).collect();
+    let mut next: Vec<AtomicU64> = (0..words).map(|_| AtomicU64::new(0)).collect();
+    set_code(&codes, start, 0);
+    cur[start as usize / 64].fetch_or(1 << (start % 64), Ordering::Relaxed);
+
+    let threads = threads.max(1);
+    let mut layers = vec![1];
+    loop {
+        let d = layers.len() as u64 - 1;
+        let chunk = words.div_ceil(threads);
+        let found: u64 = thread::scope(|s| {
+            let handles: Vec<_> = (0..threads)
+                .map(|t| {
+                    let (cur, next, codes) = (&cur, &next, &codes);
+                    let range = (t * chunk).min(words)..((t + 1) * chunk).min(words);
+                    s.spawn(move || expand(goal, range, (d + 1) % 3, cur, next, codes))
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        if found == 0 {
+            break;
+        }
+        layers.push(found);
+        std::mem::swap(&mut cur, &mut next);
+        for word in next.iter() {
+            word.store(0, Ordering::Relaxed);
+        }
+    }
+    Ok(StateSpace {
+        rows: goal.rows,
+        cols: goal.cols,
+        goal: start,
+        codes: codes.into_iter().map(|c| c.into_inner()).collect(),
+        layers,
+    })
+}
+
+// Expands the boards of the current layer in the given words,
+// returns how many boards it put in the next layer.
+fn expand(
+    grid: &Grid,
+    words: std::ops::Range<usize>,
+    code: u64,
+    cur: &[AtomicU64],
+    next: &[AtomicU64],
+    codes: &[AtomicU64],
+) -> u64 {
+    let n = grid.data.len();
+    let mut perm = [0; 20];
+    let mut found = 0;
+    for w in words {
+        let mut bits = cur[w].load(Ordering::Relaxed);
+        while bits != 0 {
+            let rank = w as u64 * 64 + bits.trailing_zeros() as u64;
+            bits &= bits - 1;
+            RANKING.unrank(rank, &mut perm[..n]);
+            for child in neighbours(grid, &mut perm[..n]) {
+                if claim(codes, child, code) {
+                    next[child as usize / 64].fetch_or(1 << (child % 64), Ordering::Relaxed);
+                    found += 1;
+                }
+            }
+        }
+    }
+    found
+}
+
+// Ranks of the boards one move away, perm is left as it was.
+fn neighbours(grid: &Grid, perm: &mut [u8]) -> Vec<u64> {
+    let n = perm.len();
+    let blank = perm.iter().position(|&v| v as usize == n - 1).unwrap();
+    next_to(grid.rows, grid.cols, blank)
+        .into_iter()
+        .map(|q| {
+            perm.swap(blank, q);
+            let rank = RANKING.rank(perm);
+            perm.swap(blank, q);
+            rank
+        })
+        .collect()
+}
+
+// The cells a blank in the given cell can move to.
+fn next_to(rows: i32, cols: i32, cell: usize) -> Vec<usize> {
+    let (x, y) = (cell as i32 / cols, cell as i32 % cols);
+    [(-1, 0), (1, 0), (0, -1), (0, 1)]
+        .iter()
+        .map(|(dx, dy)| (x + dx, y + dy))
+        .filter(|&(nx, ny)| nx >= 0 && nx < rows && ny >= 0 && ny < cols)
+        .map(|(nx, ny)| (nx * cols + ny) as usize)
+        .collect()
+}
+
+fn set_code(codes: &[AtomicU64], rank: u64, code: u64) {
+    let shift = 2 * (rank % 32);
+    let word = &codes[rank as usize / 32];
+    let old = word.load(Ordering::Relaxed);
+    word.store(old & !(3 << shift) | code << shift, Ordering::Relaxed);
+}
+
+// Sets the code of an unseen board, false if it was seen.
+fn claim(codes: &[AtomicU64], rank: u64, code: u64) -> bool {
+    let shift = 2 * (rank % 32);
+    let word = &codes[rank as usize / 32];
+    let mut old = word.load(Ordering::Relaxed);
+    loop {
+        if (old >> shift) & 3 != UNSEEN {
+            return false;
+        }
+        let new = old & !(3 << shift) | code << shift;
+        match word.compare_exchange_weak(old, new, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return true,
+            Err(actual) => old = actual,
+        }
+    }
+}
+
+impl StateSpace {
+    pub fn layers(&self) -> &[u64] {
+        &self.layers
+    }
+
+    pub fn reachable(&self) -> u64 {
+        self.layers.iter().sum()
+    }
+
+    // Distance of the boards farthest from the goal.
+    pub fn radius(&self) -> i32 {
+        self.layers.len() as i32 - 1
+    }
+
+    fn code(&self, rank: u64) -> u64 {
+        (self.codes[rank as usize / 32] >> (2 * (rank % 32))) & 3
+    }
+
+    // Moves from the board to the goal, None if the board does not
+    // fit the space or cannot reach the goal.
+    pub fn distance(&self, grid: &Grid) -> Option<i32> {
+        if (grid.rows, grid.cols) != (self.rows, self.cols) {
+            return None;
+        }
+        let mut perm = grid_perm(grid).ok()?;
+        let mut rank = RANKING.rank(&perm);
+        let mut code = self.code(rank);
+        if code == UNSEEN {
+            return None;
+        }
+        let n = perm.len();
+        let mut d = 0;
+        while rank != self.goal {
+            let blank = perm.iter().position(|&v| v as usize == n - 1)?;
+            let closer = next_to(self.rows, self.cols, blank)
+                .into_iter()
+                .find_map(|q| {
+                    perm.swap(blank, q);
+                    let r = RANKING.rank(&perm);
+                    perm.swap(blank, q);
+                    (self.code(r) == (code + 2) % 3).then_some((q, r))
+                });
+            let (q, r) = closer?;
+            perm.swap(blank, q);
+            rank = r;
+            code = (code + 2) % 3;
+            d += 1;
+        }
+        Some(d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::astar::seq;
+    use crate::utils::helpers::weighted_man_dist;
+    use crate::utils::structs::State;
+    use std::collections::{HashSet, VecDeque};
+
+    // Layers of a plain breadth first search over boards.
+    fn naive_layers(goal: &Grid) -> Vec<u64> {
+        let mut seen = HashSet::from([goal.clone()]);
+        let mut queue = VecDeque::from([(goal.clone(), 0)]);
+        let mut layers = vec![];
+        while let Some((grid, d)) = queue.pop_front() {
+            if layers.len() == d {
+                layers.push(0);
+            }
+            layers[d] += 1;
+            for (next, _) in grid.successors() {
+                if seen.insert(next.clone()) {
+                    queue.push_back((next, d + 1));
+                }
+            }
+        }
+        layers
+    }
+
+    #[test]
+    fn layers_of_2x3() {
+        let goal: Grid = "0 1 2/3 4 _".parse().unwrap();
+        let space = enumerate(&goal, 3).unwrap();
+        let known = [
+            1, 2, 3, 5, 6, 7, 10, 12, 12, 16, 23, 25, 28, 39, 44, 40, 29, 21, 18, 12, 6, 1,
+        ];
+        assert_eq!(space.layers(), known);
+        assert_eq!(space.layers(), naive_layers(&goal));
+        assert_eq!(space.reachable(), 360);
+        assert_eq!(space.radius(), 21);
+    }
+
+    #[test]
+    fn distance_matches_seq() {
+        for (rows, cols) in [(2, 3), (3, 3), (2, 4)] {
+            let goal = Grid::rand_rect_with_seed(rows, cols, 2);
+            let space = enumerate(&goal, 2).unwrap();
+            assert_eq!(space.distance(&goal), Some(0));
+            for seed in 0..30 {
+                let start = goal.rand_actions_with_seed(50, seed);
+                let best = seq::astar(&start, &goal, weighted_man_dist).unwrap().g;
+                assert_eq!(space.distance(&start), Some(best), "{}", start);
+            }
+        }
+    }
+
+    #[test]
+    fn distance_of_other_boards() {
+        let goal = Grid::rand_rect_with_seed(3, 3, 2);
+        let space = enumerate(&goal, 2).unwrap();
+        // a swap of two tiles changes the parity, it cannot be solved
+        let mut odd = goal.clone();
+        let tiles: Vec<usize> = (0..9).filter(|&i| odd.data[i].is_some()).take(2).collect();
+        odd.data.swap(tiles[0], tiles[1]);
+        assert_eq!(space.distance(&odd), None);
+        assert_eq!(space.distance(&Grid::rand_rect_with_seed(2, 4, 0)), None);
+    }
+}
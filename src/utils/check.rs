@@ -0,0 +1,275 @@
+// Empirical check of a heuristic on sampled states.
+// States are sampled by random walks from the goal. Their optimal cost
+// comes either from seq A* with a reference heuristic that has to be
+// admissible (weighted_man_dist for the puzzle, octile for maps, or a zero
+// heuristic), or from a table of exact distances built by a backward
+// search from the goal, which is feasible for puzzles up to 3x3 or 2x5.
+// Reported are
+// - inadmissible: samples with h(s) > h*(s), the searches may return
+//   suboptimal solutions
+// - inconsistent: edges s -> t with h(s) > c(s, t) + h(t), closed states
+//   may have to be reopened, which dpa and hda do, but at extra cost
+// - accuracy: mean h(s) / h*(s), mean h*(s) - h(s) and the
+//   number of samples with h(s) = h*(s)
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::astar::seq;
+use crate::error::{Error, Result};
+use crate::utils::cost::Cost;
+use crate::utils::heuristic::Heuristic;
+use crate::utils::structs::{Grid, Limit, State};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CheckReport {
+    pub samples: usize,
+    pub edges: usize,
+    pub inadmissible: usize,
+    pub inconsistent: usize,
+    // Largest h(s) - h*(s) and h(s) - c(s, t) - h(t) seen, 0 without violations.
+    pub max_overestimate: f64,
+    pub max_inconsistency: f64,
+    // Over the samples with h*(s) > 0
+    pub mean_ratio: f64,
+    pub mean_error: f64,
+    pub exact: usize,
+}
+
+impl CheckReport {
+    pub fn admissible(&self) -> bool {
+        self.inadmissible == 0
+    }
+
+    pub fn consistent(&self) -> bool {
+        self.inconsistent == 0
+    }
+}
+
+// n states, each the end of a walk of at most len random moves from goal.
+// The goal itself is sampled as well when n > 0.
+pub fn walk_samples<S: State>(goal: &S, n: usize, len: usize, seed: u64) -> Vec<S> {
+    let mut r = StdRng::seed_from_u64(seed);
+    let mut samples = Vec::with_capacity(n);
+    if n > 0 {
+        samples.push(goal.clone());
+    }
+    while samples.len() < n {
+        let mut state = goal.clone();
+        for _ in 0..r.gen_range(1..=len.max(1)) {
+            let succs = state.successors();
+            if succs.is_empty() {
+                break;
+            }
+            state = succs[r.gen_range(0..succs.len())].0.clone();
+        }
+        samples.push(state);
+    }
+    samples
+}
+
+// Puzzle samples: walk i is rand_actions_with_seed(len, seed + i).
+pub fn grid_samples(goal: &Grid, n: usize, len: i32, seed: u64) -> Vec<Grid> {
+    (0..n as u64)
+        .map(|i| goal.rand_actions_with_seed(len, seed + i))
+        .collect()
+}
+
+// Exact cost to the goal of every state that can reach it, by a backward
+// uniform cost search (a breadth first search for unit costs).
+// Moves of both domains are reversible at the same cost, so the distance
+// from the goal is the distance to it.
+// Fails with LimitReached once more than max_states states are found.
+pub fn exact_distances<S: State>(goal: &S, max_states: usize) -> Result<HashMap<S, S::Cost>> {
+    let mut dist: HashMap<S, S::Cost> = HashMap::new();
+    let mut states = vec![goal.clone()];
+    let mut open = BinaryHeap::new();
+    dist.insert(goal.clone(), S::Cost::ZERO);
+    open.push(Reverse((S::Cost::ZERO, 0)));
+
+    while let Some(Reverse((d, i))) = open.pop() {
+        if dist[&states[i]] < d {
+            continue;
+        }
+        for (t, c) in states[i].successors() {
+            let dt = d + c;
+            if dist.get(&t).is_none_or(|&old| dt.improves(old)) {
+                if !dist.contains_key(&t) && dist.len() >= max_states {
+                    return Err(Error::LimitReached);
+                }
+                dist.insert(t.clone(), dt);
+                states.push(t);
+                open.push(Reverse((dt, states.len() - 1)));
+            }
+        }
+    }
+    Ok(dist)
+}
+
+// Checks h on every sample and on every edge leaving a sample, with the
+// optimal costs found by seq A* with the reference heuristic.
+// Fails with NoSolution if a sample cannot reach the goal.
+pub fn check<S, H, R>(h: &H, reference: &R, goal: &S, samples: &[S]) -> Result<CheckReport>
+where
+    S: State,
+    H: Heuristic<S> + ?Sized,
+    R: Heuristic<S> + ?Sized,
+{
+    check_with(h, goal, samples, |s| {
+        s.check_pair(goal)?;
+        seq::solve(s, goal, reference, &Limit::none())
+            .0
+            .map(|n| n.g)
+            .ok_or(Error::NoSolution)
+    })
+}
+
+// Same as check, with the optimal costs looked up in exact_distances.
+pub fn check_exact<S, H>(
+    h: &H,
+    goal: &S,
+    samples: &[S],
+    dist: &HashMap<S, S::Cost>,
+) -> Result<CheckReport>
+where
+    S: State,
+    H: Heuristic<S> + ?Sized,
+{
+    check_with(h, goal, samples, |s| {
+        dist.get(s).copied().ok_or(Error::NoSolution)
+    })
+}
+
+fn check_with<S, H, F>(h: &H, goal: &S, samples: &[S], mut opt_cost: F) -> Result<CheckReport>
+where
+    S: State,
+    H: Heuristic<S> + ?Sized,
+    F: FnMut(&S) -> Result<S::Cost>,
+{
+    let mut report = CheckReport {
+        samples: samples.len(),
+        ..CheckReport::default()
+    };
+    let (mut ratio_sum, mut error_sum, mut cnt) = (0.0, 0.0, 0);
+    for s in samples {
+        let opt = opt_cost(s)?;
+        let hs = h.h(s, goal);
+        if opt.improves(hs) {
+            report.inadmissible += 1;
+            let over = hs.to_f64() - opt.to_f64();
+            report.max_overestimate = report.max_overestimate.max(over);
+        }
+        if opt > S::Cost::ZERO {
+            ratio_sum += hs.to_f64() / opt.to_f64();
+            error_sum += opt.to_f64() - hs.to_f64();
+            cnt += 1;
+            if !opt.improves(hs) && !hs.improves(opt) {
+                report.exact += 1;
+            }
+        }
+
+        for (t, c) in s.successors() {
+            report.edges += 1;
+            let bound = c + h.h(&t, goal);
+            if bound.improves(hs) {
+                report.inconsistent += 1;
+                let over = hs.to_f64() - bound.to_f64();
+                report.max_inconsistency = report.max_inconsistency.max(over);
+            }
+        }
+    }
+    if cnt > 0 {
+        report.mean_ratio = ratio_sum / cnt as f64;
+        report.mean_error = error_sum / cnt as f64;
+    }
+    Ok(report)
+}
+
+pub fn print_report(name: &str, report: &CheckReport) {
+    println!(
+        "{}: {} samples, {} edges",
+        name, report.samples, report.edges
+    );
+    println!(
+        "- inadmissible: {} (max overestimate {})",
+        report.inadmissible, report.max_overestimate
+    );
+    println!(
+        "- inconsistent: {} (max {})",
+        report.inconsistent, report.max_inconsistency
+    );
+    println!(
+        "- h/h*: {:.3}, h* - h: {:.3}, exact: {}",
+        report.mean_ratio, report.mean_error, report.exact
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::bfs::enumerate;
+    use crate::utils::helpers::{man_dist, misplaced, weighted_man_dist};
+    use crate::utils::structs::TileCost;
+
+    // The backward search and the breadth first enumeration agree on
+    // how many boards are at each distance, and on the distance of
+    // every board of 2x3 and every 50th of 3x3.
+    #[test]
+    fn exact_distances_match_enumeration() {
+        for (rows, cols, step) in [(2, 3, 1), (3, 3, 50)] {
+            let goal = Grid::rand_rect_with_seed(rows, cols, 0);
+            let dist = exact_distances(&goal, 1 << 20).unwrap();
+            let space = enumerate(&goal, 2).unwrap();
+            let mut layers = vec![0; space.layers().len()];
+            for &d in dist.values() {
+                layers[d as usize] += 1;
+            }
+            assert_eq!(layers, space.layers());
+            for (state, &d) in dist.iter().step_by(step) {
+                assert_eq!(space.distance(state), Some(d));
+            }
+        }
+    }
+
+    #[test]
+    fn exact_distances_limit() {
+        let goal = Grid::rand_rect_with_seed(3, 3, 0);
+        assert!(matches!(
+            exact_distances(&goal, 1000),
+            Err(Error::LimitReached)
+        ));
+    }
+
+    #[test]
+    fn verdicts() {
+        let goal = Grid::rand_rect_with_seed(2, 3, 0);
+        let dist = exact_distances(&goal, 1 << 20).unwrap();
+        let samples = grid_samples(&goal, 100, 20, 0);
+
+        let report = check_exact(&weighted_man_dist, &goal, &samples, &dist).unwrap();
+        assert!(report.admissible() && report.consistent());
+        assert_eq!(report.samples, 100);
+        assert!(report.mean_ratio > 0.0 && report.mean_ratio <= 1.0);
+
+        // man_dist counts the blank, one move away it already says 2.
+        let report = check_exact(&man_dist, &goal, &samples, &dist).unwrap();
+        assert!(!report.admissible());
+        assert!(report.max_overestimate >= 1.0);
+
+        let report = check(&misplaced, &weighted_man_dist, &goal, &samples).unwrap();
+        assert!(report.admissible() && report.consistent());
+    }
+
+    #[test]
+    fn heavy_costs() {
+        let goal = Grid::rand_rect_with_seed(2, 3, 0).with_cost(TileCost::Heavy);
+        let dist = exact_distances(&goal, 1 << 20).unwrap();
+        let samples = walk_samples(&goal, 50, 20, 1);
+        let report = check_exact(&weighted_man_dist, &goal, &samples, &dist).unwrap();
+        assert!(report.admissible() && report.consistent());
+        let report = check(&weighted_man_dist, &weighted_man_dist, &goal, &samples).unwrap();
+        assert!(report.admissible());
+    }
+}
@@ -0,0 +1,220 @@
+// Heuristics built from other heuristics.
+// - Max: the largest estimate, admissible and consistent if all parts are.
+// - Sum: the sum of the estimates, admissible if every move is charged to
+//   at most one part, e.g. PartialManDist over disjoint sets of tiles.
+// - Weighted: a weighted sum, w * h with w > 1 gives weighted A*,
+//   weights summing to at most 1 keep admissible parts admissible.
+// The parts are shared through Arcs, so one table can be used by several
+// combinations.
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use crate::utils::cost::Cost;
+use crate::utils::heuristic::Heuristic;
+use crate::utils::structs::State;
+
+#[derive(Clone)]
+pub struct Max<S: State> {
+    parts: Vec<Arc<dyn Heuristic<S>>>,
+}
+
+impl<S: State> Max<S> {
+    pub fn new() -> Self {
+        Max { parts: vec![] }
+    }
+
+    pub fn with<H: Heuristic<S> + 'static>(self, h: H) -> Self {
+        self.with_shared(Arc::new(h))
+    }
+
+    pub fn with_shared(mut self, h: Arc<dyn Heuristic<S>>) -> Self {
+        self.parts.push(h);
+        self
+    }
+}
+
+impl<S: State> Default for Max<S> {
+    fn default() -> Self {
+        Max::new()
+    }
+}
+
+impl<S: State> Debug for Max<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Max({} parts)", self.parts.len())
+    }
+}
+
+// Zero without parts.
+impl<S: State> Heuristic<S> for Max<S> {
+    fn h(&self, state: &S, goal: &S) -> S::Cost {
+        self.parts
+            .iter()
+            .map(|p| p.h(state, goal))
+            .max()
+            .unwrap_or(S::Cost::ZERO)
+    }
+}
+
+#[derive(Clone)]
+pub struct Sum<S: State> {
+    parts: Vec<Arc<dyn Heuristic<S>>>,
+}
+
+impl<S: State> Sum<S> {
+    pub fn new() -> Self {
+        Sum { parts: vec![] }
+    }
+
+    pub fn with<H: Heuristic<S> + 'static>(self, h: H) -> Self {
+        self.with_shared(Arc::new(h))
+    }
+
+    pub fn with_shared(mut self, h: Arc<dyn Heuristic<S>>) -> Self {
+        self.parts.push(h);
+        self
+    }
+}
+
+impl<S: State> Default for Sum<S> {
+    fn default() -> Self {
+        Sum::new()
+    }
+}
+
+impl<S: State> Debug for Sum<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sum({} parts)", self.parts.len())
+    }
+}
+
+impl<S: State> Heuristic<S> for Sum<S> {
+    fn h(&self, state: &S, goal: &S) -> S::Cost {
+        self.parts
+            .iter()
+            .fold(S::Cost::ZERO, |acc, p| acc + p.h(state, goal))
+    }
+}
+
+#[derive(Clone)]
+pub struct Weighted<S: State> {
+    parts: Vec<(f64, Arc<dyn Heuristic<S>>)>,
+}
+
+impl<S: State> Weighted<S> {
+    pub fn new() -> Self {
+        Weighted { parts: vec![] }
+    }
+
+    pub fn with<H: Heuristic<S> + 'static>(self, weight: f64, h: H) -> Self {
+        self.with_shared(weight, Arc::new(h))
+    }
+
+    pub fn with_shared(mut self, weight: f64, h: Arc<dyn Heuristic<S>>) -> Self {
+        self.parts.push((weight, h));
+        self
+    }
+}
+
+impl<S: State> Default for Weighted<S> {
+    fn default() -> Self {
+        Weighted::new()
+    }
+}
+
+impl<S: State> Debug for Weighted<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let weights: Vec<f64> = self.parts.iter().map(|(w, _)| *w).collect();
+        write!(f, "Weighted({:?})", weights)
+    }
+}
+
+// The sum is taken in f64 and converted once, integer costs round down.
+impl<S: State> Heuristic<S> for Weighted<S> {
+    fn h(&self, state: &S, goal: &S) -> S::Cost {
+        let sum = self
+            .parts
+            .iter()
+            .map(|(w, p)| w * p.h(state, goal).to_f64())
+            .sum();
+        S::Cost::from_f64(sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::check::{check, grid_samples};
+    use crate::utils::cost::OrdF64;
+    use crate::utils::helpers::{misplaced, weighted_man_dist};
+    use crate::utils::heuristic::PartialManDist;
+    use crate::utils::map::{octile, GridMap, MapState};
+    use crate::utils::structs::{Grid, TileCost};
+
+    fn verdict<H: Heuristic<Grid>>(h: &H, cost: TileCost) -> (bool, bool) {
+        let goal = Grid::rand_with_seed(3, 5).with_cost(cost);
+        let samples = grid_samples(&goal, 60, 30, 0);
+        let report = check(h, &weighted_man_dist, &goal, &samples).unwrap();
+        (report.admissible(), report.consistent())
+    }
+
+    #[test]
+    fn max_of_admissible_parts() {
+        let h = Max::new().with(weighted_man_dist).with(misplaced);
+        assert_eq!(verdict(&h, TileCost::Unit), (true, true));
+        let goal = Grid::rand_with_seed(3, 5);
+        let state = goal.rand_actions_with_seed(20, 1);
+        let expected = weighted_man_dist(&state, &goal).max(misplaced(&state, &goal));
+        assert_eq!(h.h(&state, &goal), expected);
+        assert_eq!(Max::<Grid>::new().h(&state, &goal), 0);
+    }
+
+    #[test]
+    fn sum_of_disjoint_parts() {
+        for cost in [TileCost::Unit, TileCost::Heavy] {
+            let goal = Grid::rand_with_seed(3, 5).with_cost(cost);
+            let h = Sum::new()
+                .with(PartialManDist::new(&goal, &[0, 1, 2, 3]))
+                .with(PartialManDist::new(&goal, &[4, 5, 6, 7]));
+            assert_eq!(verdict(&h, cost), (true, true));
+            let state = goal.rand_actions_with_seed(20, 1);
+            assert_eq!(h.h(&state, &goal), weighted_man_dist(&state, &goal));
+        }
+        // Overlapping parts charge a move twice.
+        let h = Sum::new().with(weighted_man_dist).with(weighted_man_dist);
+        assert!(!verdict(&h, TileCost::Unit).0);
+    }
+
+    #[test]
+    fn weighted() {
+        let h = Weighted::new()
+            .with(0.5, weighted_man_dist)
+            .with(0.5, misplaced);
+        assert!(verdict(&h, TileCost::Unit).0);
+        let h = Weighted::new().with(2.0, weighted_man_dist);
+        assert_eq!(verdict(&h, TileCost::Unit), (false, false));
+    }
+
+    // The weighted sum is rounded down once, not per part.
+    #[test]
+    fn weighted_rounding() {
+        let goal = Grid::rand_with_seed(3, 5);
+        let three = |_: &Grid, _: &Grid| 3;
+        let h = |w: &[f64]| {
+            w.iter()
+                .fold(Weighted::new(), |h, &w| h.with(w, three))
+                .h(&goal, &goal)
+        };
+        assert_eq!(h(&[1.0]), 3);
+        assert_eq!(h(&[0.5]), 1);
+        assert_eq!(h(&[1.5]), 4);
+        assert_eq!(h(&[0.5, 0.5]), 3);
+        assert_eq!(h(&[0.4, 0.4]), 2);
+        assert_eq!(h(&[]), 0);
+
+        let map = Arc::new(GridMap::parse("height 1\nwidth 4\nmap\n....\n").unwrap());
+        let (s, g) = (map.state(0, 0), map.state(3, 0));
+        let h = Weighted::<MapState>::new().with(0.5, octile);
+        assert_eq!(h.h(&s, &g), OrdF64(1.5));
+    }
+}
@@ -0,0 +1,228 @@
+// Path cost types. Every State picks one: the sliding puzzle counts moves
+// in i32, grid maps need real valued costs for diagonal moves.
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, Sub};
+
+pub trait Cost:
+    Copy
+    + Ord
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Default
+    + Debug
+    + Display
+    + Send
+    + Sync
+    + 'static
+{
+    const ZERO: Self;
+    // Larger than every path cost, the f of "no solution yet".
+    const MAX: Self;
+
+    // self < other by more than rounding noise. Float costs of the same
+    // path summed in a different order differ in the last bits, such
+    // values count as a tie instead of an improvement.
+    fn improves(self, other: Self) -> bool;
+
+    fn to_f64(self) -> f64;
+    // Integers round down, so scaling an admissible estimate
+    // by a weight <= 1 keeps it admissible.
+    fn from_f64(x: f64) -> Self;
+    // Bits that order as the costs do, for costs that are not negative.
+    fn to_bits(self) -> u64;
+}
+
+impl Cost for i32 {
+    const ZERO: Self = 0;
+    const MAX: Self = i32::MAX;
+
+    fn improves(self, other: Self) -> bool {
+        self < other
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_f64(x: f64) -> Self {
+        x.floor() as i32
+    }
+
+    fn to_bits(self) -> u64 {
+        self as u64
+    }
+}
+
+impl Cost for i64 {
+    const ZERO: Self = 0;
+    const MAX: Self = i64::MAX;
+
+    fn improves(self, other: Self) -> bool {
+        self < other
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_f64(x: f64) -> Self {
+        x.floor() as i64
+    }
+
+    fn to_bits(self) -> u64 {
+        self as u64
+    }
+}
+
+// Relative tolerance of OrdF64::improves
+pub const EPSILON: f64 = 1e-9;
+
+// A totally ordered f64 (f64::total_cmp), usable as a cost and as a key.
+// Costs are never NaN, -0.0 and 0.0 compare equal.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct OrdF64(pub f64);
+
+impl PartialEq for OrdF64 {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OrdF64 {}
+
+impl PartialOrd for OrdF64 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OrdF64 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.0 + 0.0).total_cmp(&(other.0 + 0.0))
+    }
+}
+
+impl Hash for OrdF64 {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.0 + 0.0).to_bits().hash(state);
+    }
+}
+
+impl Add for OrdF64 {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        OrdF64(self.0 + other.0)
+    }
+}
+
+impl Sub for OrdF64 {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        OrdF64(self.0 - other.0)
+    }
+}
+
+impl Debug for OrdF64 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&self.0, f)
+    }
+}
+
+impl Display for OrdF64 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl Cost for OrdF64 {
+    const ZERO: Self = OrdF64(0.0);
+    const MAX: Self = OrdF64(f64::INFINITY);
+
+    fn improves(self, other: Self) -> bool {
+        if other.0.is_infinite() {
+            return self.0 < other.0;
+        }
+        self.0 < other.0 - EPSILON * other.0.abs().max(1.0)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.0
+    }
+
+    fn from_f64(x: f64) -> Self {
+        OrdF64(x)
+    }
+
+    // The bits of a float that is not negative order as the float,
+    // -0.0 is made 0.0 first.
+    fn to_bits(self) -> u64 {
+        (self.0 + 0.0).to_bits()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn hash(x: OrdF64) -> u64 {
+        let mut h = DefaultHasher::new();
+        x.hash(&mut h);
+        h.finish()
+    }
+
+    #[test]
+    fn signed_zeros() {
+        let (neg, pos) = (OrdF64(-0.0), OrdF64(0.0));
+        assert_eq!(neg, pos);
+        assert_eq!(neg.cmp(&pos), Ordering::Equal);
+        assert_eq!(hash(neg), hash(pos));
+        assert!(!neg.improves(pos) && !pos.improves(neg));
+        assert_eq!(neg, OrdF64::ZERO);
+    }
+
+    // NaN is no cost, but must not break the heaps: it sorts above
+    // every number, the negative one below, and equals itself.
+    #[test]
+    fn nan_is_ordered() {
+        let nan = OrdF64(f64::NAN);
+        assert!(nan > OrdF64::MAX);
+        assert!(OrdF64(-f64::NAN) < OrdF64(f64::NEG_INFINITY));
+        assert_eq!(nan, nan);
+        assert_eq!(hash(nan), hash(nan));
+        let mut xs = [nan, OrdF64(1.0), OrdF64::MAX, OrdF64(-1.0)];
+        xs.sort();
+        assert_eq!(xs[..3], [OrdF64(-1.0), OrdF64(1.0), OrdF64::MAX]);
+    }
+
+    #[test]
+    fn improves_within_epsilon() {
+        let x = OrdF64(0.1 + 0.2);
+        let y = OrdF64(0.3);
+        assert_ne!(x, y);
+        assert!(!x.improves(y) && !y.improves(x));
+        // relative to the larger cost
+        let big = OrdF64(1e6);
+        assert!(!OrdF64(1e6 - 1e-4).improves(big));
+        assert!(OrdF64(1e6 - 1e-2).improves(big));
+        // absolute below 1
+        assert!(!OrdF64(-1e-10).improves(OrdF64::ZERO));
+        assert!(OrdF64(-1e-8).improves(OrdF64::ZERO));
+        assert!(OrdF64(1.0).improves(OrdF64(1.0 + 1e-6)));
+        assert!(OrdF64(1e300).improves(OrdF64::MAX));
+        assert!(!OrdF64::MAX.improves(OrdF64::MAX));
+    }
+
+    #[test]
+    fn integers() {
+        assert!(1.improves(2) && !2.improves(2));
+        assert_eq!(i32::from_f64(2.9), 2);
+        assert_eq!(i32::from_f64(-0.5), -1);
+        assert_eq!(i64::from_f64(7.0), 7);
+    }
+}
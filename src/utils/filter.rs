@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::astar::seq;
+use crate::error::Result;
+use crate::utils::helpers::{read_tests, test_path, weighted_man_dist, write_tests};
+use crate::utils::heuristic::Heuristic;
+use crate::utils::structs::{Grid, Limit};
+
+// Deterministic difficulty metrics of one instance, measured with
+// the sequential solver so they do not depend on the machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Metrics {
+    pub id: String,
+    pub cost: i32,
+    pub expanded: i32,
+    pub generated: i32,
+}
+
+// A bucket accepts instances whose metrics fall in the given ranges.
+// Lower bounds are inclusive, upper bounds exclusive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bucket {
+    pub name: String,
+    pub min_cost: i32,
+    pub max_cost: i32,
+    pub min_expanded: i32,
+    pub max_expanded: i32,
+}
+
+impl Bucket {
+    pub fn by_expanded(name: &str, min_expanded: i32, max_expanded: i32) -> Self {
+        Bucket {
+            name: name.to_string(),
+            min_cost: 0,
+            max_cost: i32::MAX,
+            min_expanded,
+            max_expanded,
+        }
+    }
+
+    pub fn by_cost(name: &str, min_cost: i32, max_cost: i32) -> Self {
+        Bucket {
+            name: name.to_string(),
+            min_cost,
+            max_cost,
+            min_expanded: 0,
+            max_expanded: i32::MAX,
+        }
+    }
+
+    pub fn accepts(&self, m: &Metrics) -> bool {
+        m.cost >= self.min_cost
+            && m.cost < self.max_cost
+            && m.expanded >= self.min_expanded
+            && m.expanded < self.max_expanded
+    }
+}
+
+// Written next to the instance file of every bucket.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BucketMeta {
+    pub bucket: Bucket,
+    pub heuristic: String,
+    pub instances: Vec<Metrics>,
+}
+
+// Solve every instance of the pool with seq::astar, spreading
+// the instances over num_threads workers.
+// Results are returned in the order of the pool.
+pub fn solve_pool<H: Heuristic<Grid> + ?Sized>(
+    pool: &[(String, Grid, Grid)],
+    h_func: &H,
+    num_threads: usize,
+) -> Vec<Metrics> {
+    let next = AtomicUsize::new(0);
+    let res: Mutex<Vec<Option<Metrics>>> = Mutex::new(vec![None; pool.len()]);
+
+    thread::scope(|s| {
+        for _ in 0..num_threads.max(1) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= pool.len() {
+                    break;
+                }
+                let (id, start, end) = &pool[i];
+                let (node, log) = seq::solve(start, end, h_func, &Limit::none());
+                let m = Metrics {
+                    id: id.clone(),
+                    cost: node.map_or(-1, |n| n.g),
+                    expanded: log.exp_cnt,
+                    generated: log.gen_cnt,
+                };
+                res.lock().unwrap()[i] = Some(m);
+            });
+        }
+    });
+
+    res.into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|m| m.unwrap())
+        .collect()
+}
+
+// Assign every solved instance to the first bucket accepting it.
+// Unsolvable instances and instances no bucket accepts are dropped.
+pub fn classify(
+    pool: &[(String, Grid, Grid)],
+    metrics: &[Metrics],
+    buckets: &[Bucket],
+) -> Vec<Vec<usize>> {
+    let mut res = vec![vec![]; buckets.len()];
+    for (i, m) in metrics.iter().enumerate().take(pool.len()) {
+        if m.cost < 0 {
+            continue;
+        }
+        if let Some(b) = buckets.iter().position(|b| b.accepts(m)) {
+            res[b].push(i);
+        }
+    }
+    res
+}
+
+// Write one instance file per bucket, readable by read_tests,
+// plus a <name>.meta.json file with the metrics of its instances.
+pub fn write_buckets(
+    pool: &[(String, Grid, Grid)],
+    metrics: &[Metrics],
+    buckets: &[Bucket],
+    h_name: &str,
+) -> Result<()> {
+    let classes = classify(pool, metrics, buckets);
+    for (bucket, idx) in buckets.iter().zip(classes) {
+        let tests: Vec<(Grid, Grid)> = idx
+            .iter()
+            .map(|&i| (pool[i].1.clone(), pool[i].2.clone()))
+            .collect();
+        write_tests(&bucket.name, &tests)?;
+
+        let meta = BucketMeta {
+            bucket: bucket.clone(),
+            heuristic: h_name.to_string(),
+            instances: idx.iter().map(|&i| metrics[i].clone()).collect(),
+        };
+        let path = test_path(&format!("{}.meta.json", bucket.name));
+        let mut f = File::create(path)?;
+        f.write_all(serde_json::to_string_pretty(&meta)?.as_bytes())?;
+        println!("{}: {} instances", bucket.name, tests.len());
+    }
+    Ok(())
+}
+
+// Pool the given tiers and bucket them by the number of nodes
+// seq::astar expands with weighted_man_dist, which unlike wall time is
+// reproducible. The heuristic is admissible, so the costs are optimal.
+pub fn filter_tiers(tiers: &[&str], buckets: &[Bucket], num_threads: usize) -> Result<()> {
+    let mut pool = vec![];
+    for tier in tiers {
+        for (i, (s, e)) in read_tests(tier)?.into_iter().enumerate() {
+            pool.push((format!("{}.{}", tier, i), s, e));
+        }
+    }
+    let metrics = solve_pool(&pool, &weighted_man_dist, num_threads);
+    write_buckets(&pool, &metrics, buckets, "weighted_man_dist")
+}
+
+pub fn default_buckets() -> Vec<Bucket> {
+    vec![
+        Bucket::by_expanded("small", 0, 20_000),
+        Bucket::by_expanded("large", 20_000, 200_000),
+        Bucket::by_expanded("oneplus", 200_000, i32::MAX),
+    ]
+}
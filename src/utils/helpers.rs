@@ -1,9 +1,10 @@
-use crate::astar::{dpa, hda};
-use crate::utils::structs::{Grid, Node};
+use crate::error::Result;
+use crate::utils::heuristic::Heuristic;
+use crate::utils::structs::{Grid, Node, State, StateHasher};
 use serde_json;
 use std::collections::HashMap;
-use std::fmt::format;
 use std::fs::{read_to_string, File};
+use std::io::Write;
 use std::path::PathBuf;
 
 pub fn gen_tests((size, n, min, max): (i32, i32, i32, i32)) -> String {
@@ -49,38 +50,121 @@ pub fn gen_tests((size, n, min, max): (i32, i32, i32, i32)) -> String {
     serde_json::to_string(&tests).unwrap()
 }
 
-pub fn read_tests(fpath: &str) -> Vec<(Grid, Grid)> {
+// Generate the easy, medium and hard tiers.
+pub fn setup_tests() -> Result<()> {
+    let test_files = ["easy", "medium", "hard"];
+    let params = [(4, 30, 25, 30), (4, 30, 30, 35), (5, 30, 30, 35)];
+
+    for i in 0..3 {
+        let path = test_path(&format!("{}.txt", test_files[i]));
+        let mut f = File::create(path)?;
+        let tests = gen_tests(params[i]);
+        f.write_all(tests.as_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn test_path(fname: &str) -> PathBuf {
     let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    path.push(format!("src/tests/{}.txt", fpath));
+    path.push(format!("src/tests/{}", fname));
+    path
+}
 
-    let tests = read_to_string(path).unwrap();
-    serde_json::from_str(&tests).unwrap()
+// Every (start, end) pair is validated, since deserializing
+// bypasses the checks of Grid::try_new.
+pub fn read_tests(fpath: &str) -> Result<Vec<(Grid, Grid)>> {
+    let path = test_path(&format!("{}.txt", fpath));
+    let tests: Vec<(Grid, Grid)> = serde_json::from_str(&read_to_string(path)?)?;
+    for (s, e) in tests.iter() {
+        s.check_pair(e)?;
+    }
+    Ok(tests)
+}
+
+pub fn write_tests(fpath: &str, tests: &[(Grid, Grid)]) -> Result<()> {
+    let path = test_path(&format!("{}.txt", fpath));
+    let mut f = File::create(path)?;
+    f.write_all(serde_json::to_string(tests)?.as_bytes())?;
+    Ok(())
 }
 
+// Manhattan distance of all cells, the blank included, so it can
+// overestimate: neither admissible nor consistent. Kept for comparisons
+// with earlier runs, weighted_man_dist is the admissible version.
 pub fn man_dist(g1: &Grid, g2: &Grid) -> i32 {
-    // Assume grids with same size and same elements.
+    // Assume grids with same size and same elements,
+    // see Grid::check_pair. Tiles missing from g2 are skipped.
+    let coord = |i: i32| (i / g1.cols, i % g1.cols);
     let mut map = HashMap::new();
-    for i in 0..g1.size.pow(2) {
-        map.insert(g2.data[i as usize], g2.get_coord(i));
+    for i in 0..g2.data.len() as i32 {
+        map.insert(g2.data[i as usize], coord(i));
     }
     // println!("{:?}", map);
 
     let mut dist = 0;
-    for i in 0..g1.size.pow(2) {
-        let (x1, y1) = g1.get_coord(i);
-        let &(x2, y2) = map.get(&g1.data[i as usize]).unwrap();
-        dist += (x1 - x2).abs() + (y1 - y2).abs();
+    for i in 0..g1.data.len() as i32 {
+        let (x1, y1) = coord(i);
+        if let Some(&(x2, y2)) = map.get(&g1.data[i as usize]) {
+            dist += (x1 - x2).abs() + (y1 - y2).abs();
+        }
     }
     dist
 }
 
-pub fn expand(node: &Node, end_state: &Grid, h_func: fn(&Grid, &Grid) -> i32) -> Vec<Node> {
-    let states = node.state.expand();
+// Manhattan distance of the tiles weighted by their move cost,
+// admissible for every TileCost and any number of blanks since a move
+// of tile n costs cost.of(n) and brings it at most one cell closer.
+pub fn weighted_man_dist(g1: &Grid, g2: &Grid) -> i32 {
+    let coord = |i: usize| (i as i32 / g1.cols, i as i32 % g1.cols);
+    // goal[n] is the goal cell of tile n
+    let mut goal = vec![None; g2.data.len()];
+    for (i, cell) in g2.data.iter().enumerate() {
+        if let Some(n) = *cell {
+            goal[n as usize] = Some(coord(i));
+        }
+    }
+
+    let mut dist = 0;
+    for (i, cell) in g1.data.iter().enumerate() {
+        if let Some(n) = *cell {
+            if let Some(Some((x2, y2))) = goal.get(n as usize) {
+                let (x1, y1) = coord(i);
+                dist += g1.cost.of(n) * ((x1 - x2).abs() + (y1 - y2).abs());
+            }
+        }
+    }
+    dist
+}
+
+// Number of tiles out of place, the blank excluded.
+pub fn misplaced(g1: &Grid, g2: &Grid) -> i32 {
+    g1.data
+        .iter()
+        .zip(g2.data.iter())
+        .filter(|(a, b)| a.is_some() && a != b)
+        .count() as i32
+}
+
+// Derive the seed of one thread from a master seed (splitmix64),
+// so threads draw different but reproducible random streams.
+pub fn derive_seed(master: u64, thread_num: u64) -> u64 {
+    let mut z = master.wrapping_add((thread_num + 1).wrapping_mul(0x9E3779B97F4A7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+pub fn expand<S: State, H: Heuristic<S> + ?Sized>(
+    node: &Node<S>,
+    end_state: &S,
+    h_func: &H,
+) -> Vec<Node<S>> {
+    let states = node.state.successors();
     states
         .into_iter()
-        .map(|state| {
-            let g = node.g + 1;
-            let h = h_func(&state, end_state);
+        .map(|(state, cost)| {
+            let g = node.g + cost;
+            let h = h_func.h(&state, end_state);
             let f = g + h;
             // let (x, y) = state.get_coord(state.empty_idx);
             // let (px, py) = node.state.get_coord(node.state.empty_idx);
@@ -91,6 +175,8 @@ pub fn expand(node: &Node, end_state: &Grid, h_func: fn(&Grid, &Grid) -> i32) ->
                 f,
                 g,
                 h,
+                hash: 0,
+                abstract_hash: 0,
                 // prev_actions: Box::new(prev_actions),
                 // prev_node: Some(Box::new(node.clone())),
             }
@@ -98,6 +184,32 @@ pub fn expand(node: &Node, end_state: &Grid, h_func: fn(&Grid, &Grid) -> i32) ->
         .collect()
 }
 
+// expand for searches that keep hashes, the successors carry
+// theirs, updated from the hash of node where the hasher can.
+pub fn expand_hashed<S: State, T: StateHasher<S>, H: Heuristic<S> + ?Sized>(
+    node: &Node<S>,
+    end_state: &S,
+    h_func: &H,
+    hasher: &T,
+) -> Vec<Node<S>> {
+    hasher
+        .hashed_successors(&node.state, node.hash)
+        .into_iter()
+        .map(|(state, cost, hash)| {
+            let g = node.g + cost;
+            let h = h_func.h(&state, end_state);
+            Node {
+                state,
+                f: g + h,
+                g,
+                h,
+                hash,
+                abstract_hash: 0,
+            }
+        })
+        .collect()
+}
+
 // pub fn calc_receiver(node: &Node, num_threads: i32) -> i32 {
 //     1
 // }
@@ -0,0 +1,125 @@
+// Heuristics estimate the cost from a state to the goal.
+// A heuristic is built once per search and shared by all threads through
+// an Arc, so it can hold tables precomputed for the goal.
+// Functions fn(&S, &S) -> S::Cost are heuristics as well.
+use crate::utils::structs::{Grid, State};
+
+pub trait Heuristic<S: State>: Send + Sync {
+    // Heuristics precomputed for one goal may ignore the goal argument.
+    fn h(&self, state: &S, goal: &S) -> S::Cost;
+}
+
+impl<S: State, F: Fn(&S, &S) -> S::Cost + Send + Sync> Heuristic<S> for F {
+    fn h(&self, state: &S, goal: &S) -> S::Cost {
+        self(state, goal)
+    }
+}
+
+// weighted_man_dist with the goal cells of the tiles looked up in a
+// table instead of being rebuilt on every call. Gives the same values
+// as weighted_man_dist for the goal it was built for, so the blanks are
+// not counted and it is admissible.
+#[derive(Debug, Clone)]
+pub struct ManDist {
+    cols: i32,
+    // goal[n] is the goal cell of tile n
+    goal: Vec<Option<(i32, i32)>>,
+}
+
+impl ManDist {
+    pub fn new(goal: &Grid) -> Self {
+        let mut table = vec![None; goal.data.len()];
+        for (i, cell) in goal.data.iter().enumerate() {
+            if let Some(n) = *cell {
+                table[n as usize] = Some((i as i32 / goal.cols, i as i32 % goal.cols));
+            }
+        }
+        ManDist {
+            cols: goal.cols,
+            goal: table,
+        }
+    }
+}
+
+impl Heuristic<Grid> for ManDist {
+    fn h(&self, state: &Grid, _: &Grid) -> i32 {
+        let mut dist = 0;
+        for (i, cell) in state.data.iter().enumerate() {
+            if let Some(n) = *cell {
+                if let Some(Some((x2, y2))) = self.goal.get(n as usize) {
+                    let (x1, y1) = (i as i32 / self.cols, i as i32 % self.cols);
+                    dist += state.cost.of(n) * ((x1 - x2).abs() + (y1 - y2).abs());
+                }
+            }
+        }
+        dist
+    }
+}
+
+// Manhattan distance of a subset of the tiles, weighted by their move
+// cost. The blank is not counted, so sums over disjoint subsets are
+// admissible, see combinators::Sum.
+#[derive(Debug, Clone)]
+pub struct PartialManDist {
+    cols: i32,
+    // goal[n] is the goal cell of tile n if it belongs to the subset
+    goal: Vec<Option<(i32, i32)>>,
+}
+
+impl PartialManDist {
+    pub fn new(goal: &Grid, tiles: &[i32]) -> Self {
+        let mut table = vec![None; goal.data.len()];
+        for (i, cell) in goal.data.iter().enumerate() {
+            if let Some(n) = *cell {
+                if tiles.contains(&n) {
+                    table[n as usize] = Some((i as i32 / goal.cols, i as i32 % goal.cols));
+                }
+            }
+        }
+        PartialManDist {
+            cols: goal.cols,
+            goal: table,
+        }
+    }
+}
+
+impl Heuristic<Grid> for PartialManDist {
+    fn h(&self, state: &Grid, _: &Grid) -> i32 {
+        let mut dist = 0;
+        for (i, cell) in state.data.iter().enumerate() {
+            if let Some(n) = *cell {
+                if let Some((x2, y2)) = self.goal[n as usize] {
+                    let (x1, y1) = (i as i32 / self.cols, i as i32 % self.cols);
+                    dist += state.cost.of(n) * ((x1 - x2).abs() + (y1 - y2).abs());
+                }
+            }
+        }
+        dist
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::helpers::weighted_man_dist;
+    use crate::utils::structs::TileCost;
+
+    #[test]
+    fn table_matches_weighted_man_dist() {
+        for (rows, cols, blanks) in [(3, 3, 1), (4, 4, 1), (2, 5, 3)] {
+            for cost in [TileCost::Unit, TileCost::Heavy] {
+                let goal = Grid::rand_rect_with_seed(rows, cols, 1)
+                    .with_cost(cost)
+                    .with_blanks(blanks);
+                let table = ManDist::new(&goal);
+                for seed in 0..20 {
+                    let state = Grid::rand_rect_with_seed(rows, cols, seed)
+                        .with_cost(cost)
+                        .with_blanks(blanks);
+                    assert_eq!(table.h(&state, &goal), weighted_man_dist(&state, &goal));
+                }
+                assert_eq!(table.h(&goal, &goal), 0);
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+// Diagnostics for choosing a hasher: how many distinct states share a
+// key, and how evenly HDA* routing, hash % num_threads, spreads them.
+// Sample states come from a breadth first search from some state, so
+// they are close to each other like the states of one search.
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::structs::{Grid, State, StateHash, StateHasher, ZKey};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeyReport {
+    pub states: usize,
+    pub bits: u32,
+    // States minus distinct keys, and what uniform random keys would give.
+    pub collisions: usize,
+    pub expected: f64,
+    // States routed to each thread.
+    pub loads: Vec<usize>,
+    // Largest load over the mean load.
+    pub imbalance: f64,
+    // Pearson's statistic of the loads against uniform loads, about
+    // num_threads - 1 for uniform routing.
+    pub chi_square: f64,
+}
+
+// Up to max_states distinct states nearest to start.
+pub fn reachable<S: State>(start: &S, max_states: usize) -> Vec<S> {
+    let mut seen: HashSet<S> = HashSet::new();
+    let mut queue = VecDeque::new();
+    let mut states = vec![];
+    seen.insert(start.clone());
+    queue.push_back(start.clone());
+    while let Some(state) = queue.pop_front() {
+        if states.len() == max_states {
+            break;
+        }
+        for (s, _) in state.successors() {
+            if seen.insert(s.clone()) {
+                queue.push_back(s);
+            }
+        }
+        states.push(state);
+    }
+    states
+}
+
+// Keys of the full width of the hasher.
+pub fn grid_report<T: StateHash>(states: &[Grid], hasher: &T, num_threads: usize) -> KeyReport {
+    report_with(
+        states,
+        T::Key::BITS,
+        |s| s.hash_with(hasher),
+        |s| s.hash_with(hasher).fold(),
+        num_threads,
+    )
+}
+
+// Keys as searches keep them, 64 bits.
+pub fn state_report<S: State, T: StateHasher<S>>(
+    states: &[S],
+    hasher: &T,
+    num_threads: usize,
+) -> KeyReport {
+    let hash = |s: &S| hasher.hash(s);
+    report_with(states, 64, hash, hash, num_threads)
+}
+
+fn report_with<S: State, K: Eq + Hash>(
+    states: &[S],
+    bits: u32,
+    key: impl Fn(&S) -> K,
+    route: impl Fn(&S) -> u64,
+    num_threads: usize,
+) -> KeyReport {
+    let num_threads = num_threads.max(1);
+    let distinct: HashSet<&S> = states.iter().collect();
+    let mut keys: HashMap<K, usize> = HashMap::new();
+    let mut loads = vec![0; num_threads];
+    for s in distinct.iter() {
+        *keys.entry(key(s)).or_default() += 1;
+        loads[(route(s) % num_threads as u64) as usize] += 1;
+    }
+    let n = distinct.len() as f64;
+    let mean = n / num_threads as f64;
+    let max = loads.iter().max().copied().unwrap_or(0) as f64;
+    KeyReport {
+        states: distinct.len(),
+        bits,
+        collisions: distinct.len() - keys.len(),
+        // n (n - 1) / 2 pairs, each equal with probability 2^-bits
+        expected: n * (n - 1.0) / 2.0 / 2f64.powi(bits as i32),
+        imbalance: if mean > 0.0 { max / mean } else { 0.0 },
+        chi_square: if mean > 0.0 {
+            loads
+                .iter()
+                .map(|&l| (l as f64 - mean).powi(2) / mean)
+                .sum()
+        } else {
+            0.0
+        },
+        loads,
+    }
+}
+
+pub fn print_report(name: &str, report: &KeyReport) {
+    println!(
+        "{}: {} states, {} bit keys",
+        name, report.states, report.bits
+    );
+    println!(
+        "- collisions: {} (uniform keys: {:.3})",
+        report.collisions, report.expected
+    );
+    println!(
+        "- {} threads: imbalance {:.3}, chi square {:.1}",
+        report.loads.len(),
+        report.imbalance,
+        report.chi_square
+    );
+}
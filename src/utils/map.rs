@@ -0,0 +1,398 @@
+// Grid map pathfinding, the second search domain next to the sliding puzzle.
+// Maps and scenarios are read in the Moving AI benchmark formats
+// (https://movingai.com/benchmarks/formats.html).
+// A cardinal move costs 1, a diagonal move sqrt(2).
+use rand::{Rng, SeedableRng};
+use std::fmt::{Debug, Formatter};
+use std::fs::read_to_string;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+use crate::utils::cost::OrdF64;
+use crate::utils::structs::{State, StateHasher};
+
+pub const CARDINAL: OrdF64 = OrdF64(1.0);
+pub const DIAGONAL: OrdF64 = OrdF64(std::f64::consts::SQRT_2);
+// Cardinal moves first, a move and its opposite differ in the last bit.
+const DIRECTIONS: [(i32, i32); 8] = [
+    (0, -1),
+    (0, 1),
+    (-1, 0),
+    (1, 0),
+    (-1, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+];
+// Side of the square blocks the abstract hasher maps to one value.
+pub const ABSTRACT_BLOCK: i32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    Four,
+    Eight,
+}
+
+#[derive(Debug, Clone)]
+pub struct GridMap {
+    pub width: i32,
+    pub height: i32,
+    pub connectivity: Connectivity,
+    // passable[y * width + x]
+    passable: Vec<bool>,
+}
+
+impl GridMap {
+    // Parse a .map file. Only '.', 'G' and 'S' are passable,
+    // as in the Moving AI benchmarks. Maps are 8-connected.
+    pub fn parse(s: &str) -> Result<GridMap> {
+        let mut lines = s.lines();
+        let (mut width, mut height) = (None, None);
+        for line in lines.by_ref() {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("map"), None) => break,
+                (Some("height"), Some(n)) => height = n.parse().ok(),
+                (Some("width"), Some(n)) => width = n.parse().ok(),
+                _ => {}
+            }
+        }
+        let (width, height): (i32, i32) = match (width, height) {
+            (Some(w), Some(h)) if w > 0 && h > 0 => (w, h),
+            _ => return Err(Error::Parse("missing map width or height".to_string())),
+        };
+
+        let mut passable = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            let row = lines
+                .next()
+                .ok_or_else(|| Error::Parse(format!("map ends at row {}", y)))?
+                .trim_end();
+            if row.len() != width as usize {
+                return Err(Error::Parse(format!(
+                    "map row {} has {} cells, expected {}",
+                    y,
+                    row.len(),
+                    width
+                )));
+            }
+            passable.extend(row.chars().map(|c| matches!(c, '.' | 'G' | 'S')));
+        }
+        Ok(GridMap {
+            width,
+            height,
+            connectivity: Connectivity::Eight,
+            passable,
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<GridMap> {
+        GridMap::parse(&read_to_string(path)?)
+    }
+
+    pub fn with_connectivity(mut self, connectivity: Connectivity) -> Self {
+        self.connectivity = connectivity;
+        self
+    }
+
+    pub fn passable(&self, x: i32, y: i32) -> bool {
+        x >= 0
+            && x < self.width
+            && y >= 0
+            && y < self.height
+            && self.passable[(y * self.width + x) as usize]
+    }
+
+    pub fn state(self: &Arc<Self>, x: i32, y: i32) -> MapState {
+        MapState {
+            x,
+            y,
+            map: self.clone(),
+        }
+    }
+}
+
+// A position on a map. States compare and hash by position only,
+// the map is shared by all states of one search.
+#[derive(Clone)]
+pub struct MapState {
+    pub x: i32,
+    pub y: i32,
+    pub map: Arc<GridMap>,
+}
+
+impl PartialEq for MapState {
+    fn eq(&self, other: &Self) -> bool {
+        (self.x, self.y) == (other.x, other.y)
+    }
+}
+
+impl Eq for MapState {}
+
+impl Hash for MapState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.x, self.y).hash(state);
+    }
+}
+
+impl Debug for MapState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+impl State for MapState {
+    type Cost = OrdF64;
+    type Hasher = CoordHasher;
+    type AbstractHasher = CoordHasher;
+
+    fn successors(&self) -> Vec<(Self, OrdF64)> {
+        self.moves()
+            .into_iter()
+            .map(|(s, c, _, _)| (s, c))
+            .collect()
+    }
+
+    // Move i goes in direction i of DIRECTIONS, its opposite is i ^ 1.
+    // Diagonal moves may not cut corners: both cells next to
+    // the move have to be passable.
+    fn moves(&self) -> Vec<(Self, OrdF64, u8, Option<u8>)> {
+        let map = &self.map;
+        let mut moves = Vec::with_capacity(8);
+        for (i, &(dx, dy)) in DIRECTIONS.iter().enumerate() {
+            let diagonal = dx != 0 && dy != 0;
+            if diagonal && map.connectivity == Connectivity::Four {
+                break;
+            }
+            if map.passable(self.x + dx, self.y + dy)
+                && (!diagonal
+                    || map.passable(self.x + dx, self.y) && map.passable(self.x, self.y + dy))
+            {
+                let cost = if diagonal { DIAGONAL } else { CARDINAL };
+                let state = map.state(self.x + dx, self.y + dy);
+                moves.push((state, cost, i as u8, Some(i as u8 ^ 1)));
+            }
+        }
+        moves
+    }
+
+    fn check_pair(&self, end: &Self) -> Result<()> {
+        if !Arc::ptr_eq(&self.map, &end.map) {
+            return Err(Error::Mismatch("states of different maps".to_string()));
+        }
+        for s in [self, end] {
+            if !s.map.passable(s.x, s.y) {
+                return Err(Error::Blocked((s.x, s.y)));
+            }
+        }
+        Ok(())
+    }
+
+    fn hasher(&self) -> CoordHasher {
+        CoordHasher::new(self.map.width, self.map.height, 1)
+    }
+
+    fn abstract_hasher(&self) -> CoordHasher {
+        CoordHasher::new(self.map.width, self.map.height, ABSTRACT_BLOCK)
+    }
+
+    // Big endian, so packed states sort like (y, x).
+    fn pack(&self, out: &mut Vec<u8>) {
+        out.extend(self.y.to_be_bytes());
+        out.extend(self.x.to_be_bytes());
+    }
+
+    fn unpack(bytes: &[u8], like: &Self) -> Self {
+        let y = i32::from_be_bytes(bytes[..4].try_into().unwrap());
+        let x = i32::from_be_bytes(bytes[4..8].try_into().unwrap());
+        like.map.state(x, y)
+    }
+}
+
+// Zobrist hashing of positions: one random value per column and per row,
+// xored. With block > 1, all cells of a block x block square hash alike,
+// which keeps most successors on the thread of their parent.
+#[derive(Debug, Clone)]
+pub struct CoordHasher {
+    pub xs: Vec<u32>,
+    pub ys: Vec<u32>,
+    pub block: i32,
+}
+
+impl CoordHasher {
+    pub fn new(width: i32, height: i32, block: i32) -> Self {
+        let mut r = rand::rngs::StdRng::seed_from_u64(420);
+        let block = block.max(1);
+        let xs = (0..=width / block).map(|_| r.gen()).collect();
+        let ys = (0..=height / block).map(|_| r.gen()).collect();
+        CoordHasher { xs, ys, block }
+    }
+}
+
+impl StateHasher<MapState> for CoordHasher {
+    fn hash(&self, state: &MapState) -> u64 {
+        (self.xs[(state.x / self.block) as usize] ^ self.ys[(state.y / self.block) as usize]) as u64
+    }
+}
+
+// Exact distance on an empty 8-connected map.
+pub fn octile(s1: &MapState, s2: &MapState) -> OrdF64 {
+    let dx = (s1.x - s2.x).abs();
+    let dy = (s1.y - s2.y).abs();
+    OrdF64(DIAGONAL.0 * dx.min(dy) as f64 + CARDINAL.0 * (dx.max(dy) - dx.min(dy)) as f64)
+}
+
+// Exact distance on an empty 4-connected map,
+// not admissible on 8-connected ones.
+pub fn manhattan(s1: &MapState, s2: &MapState) -> OrdF64 {
+    OrdF64(CARDINAL.0 * ((s1.x - s2.x).abs() + (s1.y - s2.y).abs()) as f64)
+}
+
+// One line of a .scen file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    pub bucket: i32,
+    pub map: String,
+    pub width: i32,
+    pub height: i32,
+    pub start: (i32, i32),
+    pub goal: (i32, i32),
+    // Optimal 8-connected path length in cells.
+    pub optimal: f64,
+}
+
+pub fn parse_scen(s: &str) -> Result<Vec<Scenario>> {
+    let bad = |i: usize| Error::Parse(format!("bad scenario on line {}", i + 1));
+    let mut scens = vec![];
+    for (i, line) in s.lines().enumerate() {
+        if line.starts_with("version") || line.trim().is_empty() {
+            continue;
+        }
+        let cols: Vec<&str> = line.split('\t').collect();
+        if cols.len() != 9 {
+            return Err(bad(i));
+        }
+        let num = |j: usize| cols[j].trim().parse::<i32>().map_err(|_| bad(i));
+        scens.push(Scenario {
+            bucket: num(0)?,
+            map: cols[1].to_string(),
+            width: num(2)?,
+            height: num(3)?,
+            start: (num(4)?, num(5)?),
+            goal: (num(6)?, num(7)?),
+            optimal: cols[8].trim().parse().map_err(|_| bad(i))?,
+        });
+    }
+    Ok(scens)
+}
+
+pub fn read_scen<P: AsRef<Path>>(path: P) -> Result<Vec<Scenario>> {
+    parse_scen(&read_to_string(path)?)
+}
+
+// Start and goal state of a scenario on its map.
+pub fn scen_states(map: &Arc<GridMap>, scen: &Scenario) -> (MapState, MapState) {
+    (
+        map.state(scen.start.0, scen.start.1),
+        map.state(scen.goal.0, scen.goal.1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::astar::seq;
+    use crate::utils::cost::Cost;
+
+    // A wall below the middle of the top row. Going around it takes
+    // 6 moves, as no move may cut one of its corners.
+    const WALL: &str = "type octile\nheight 3\nwidth 5\nmap\n.....\n.@@@.\n.....\n";
+
+    const SCEN: &str = "version 1\n\
+        0\twall.map\t5\t3\t2\t0\t2\t2\t6\n\
+        0\twall.map\t5\t3\t0\t0\t4\t2\t6\n\
+        0\twall.map\t5\t3\t0\t0\t0\t0\t0\n";
+
+    fn dijkstra(s: &MapState, g: &MapState) -> OrdF64 {
+        let zero = |_: &MapState, _: &MapState| OrdF64(0.0);
+        seq::astar(s, g, zero).unwrap().g
+    }
+
+    fn cells(map: &Arc<GridMap>) -> Vec<MapState> {
+        (0..map.height)
+            .flat_map(|y| (0..map.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| map.passable(x, y))
+            .map(|(x, y)| map.state(x, y))
+            .collect()
+    }
+
+    #[test]
+    fn parse_map() {
+        let map = GridMap::parse(WALL).unwrap();
+        assert_eq!((map.width, map.height), (5, 3));
+        assert_eq!(map.connectivity, Connectivity::Eight);
+        assert!(map.passable(0, 1) && !map.passable(1, 1) && !map.passable(3, 1));
+        assert!(!map.passable(-1, 0) && !map.passable(5, 0) && !map.passable(0, 3));
+        for bad in [
+            "height 3\nmap\n.....\n",
+            "height 2\nwidth 5\nmap\n.....\n",
+            "height 2\nwidth 5\nmap\n.....\n....\n",
+        ] {
+            assert!(GridMap::parse(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn scenarios_have_known_costs() {
+        let map = Arc::new(GridMap::parse(WALL).unwrap());
+        let scens = parse_scen(SCEN).unwrap();
+        assert_eq!(scens.len(), 3);
+        assert_eq!(scens[0].start, (2, 0));
+        assert_eq!(scens[0].goal, (2, 2));
+        for scen in scens.iter() {
+            let (start, goal) = scen_states(&map, scen);
+            let cost = seq::astar(&start, &goal, octile).unwrap().g;
+            assert!((cost.0 - scen.optimal).abs() < 1e-6, "{:?}: {}", scen, cost);
+        }
+        assert!(parse_scen("0\twall.map\t5\t3\t2\t0\t2\n").is_err());
+        assert!(parse_scen("0\twall.map\t5\tx\t2\t0\t2\t2\t6\n").is_err());
+    }
+
+    #[test]
+    fn no_corner_cutting() {
+        let map = Arc::new(GridMap::parse("height 2\nwidth 2\nmap\n..\n@.\n").unwrap());
+        let (start, goal) = (map.state(0, 0), map.state(1, 1));
+        assert!(start.successors().iter().all(|(s, _)| *s != goal));
+        assert_eq!(dijkstra(&start, &goal), OrdF64(2.0));
+
+        let open = Arc::new(GridMap::parse("height 2\nwidth 2\nmap\n..\n..\n").unwrap());
+        assert_eq!(dijkstra(&open.state(0, 0), &open.state(1, 1)), DIAGONAL);
+        let four = Arc::new(
+            GridMap::parse("height 2\nwidth 2\nmap\n..\n..\n")
+                .unwrap()
+                .with_connectivity(Connectivity::Four),
+        );
+        assert_eq!(dijkstra(&four.state(0, 0), &four.state(1, 1)), OrdF64(2.0));
+    }
+
+    // octile never overestimates, and A* with it finds the costs
+    // of a blind search, between all pairs of cells.
+    #[test]
+    fn octile_is_admissible() {
+        let map = Arc::new(
+            GridMap::parse("height 5\nwidth 6\nmap\n......\n.@@@.@\n...@..\n@.@...\n......\n")
+                .unwrap(),
+        );
+        let cells = cells(&map);
+        for s in cells.iter() {
+            for g in cells.iter() {
+                let exact = dijkstra(s, g);
+                assert!(!exact.improves(octile(s, g)), "{:?} to {:?}", s, g);
+                let cost = seq::astar(s, g, octile).unwrap().g;
+                assert!(!cost.improves(exact) && !exact.improves(cost));
+            }
+        }
+    }
+}
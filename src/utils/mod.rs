@@ -1,2 +1,16 @@
+pub mod analysis;
+pub mod bfs;
+pub mod check;
+pub mod combinators;
+pub mod cost;
+pub mod filter;
 pub mod helpers;
+pub mod heuristic;
+pub mod keys;
+pub mod map;
+pub mod net;
+pub mod pdb;
+pub mod rank;
 pub mod structs;
+pub mod table;
+pub mod trace;
@@ -0,0 +1,345 @@
+// Sockets between the processes of a distributed search, TCP or Unix
+// domain sockets behind one type, and the frames sent over them.
+// Unix domain sockets are only there on Unix targets.
+// A frame is the length of its body as a u32, a tag byte and the body.
+// Numbers are big endian like in the files of the external search.
+use std::fmt::{Display, Formatter};
+#[cfg(unix)]
+use std::fs::remove_file;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+
+// Larger frames are taken for garbage.
+const MAX_FRAME: usize = 1 << 24;
+
+// Written as tcp:<ip>:<port> or unix:<path>.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Addr {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl FromStr for Addr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bad = || Error::Parse(format!("bad address {}", s));
+        match s.split_once(':') {
+            Some(("tcp", addr)) => Ok(Addr::Tcp(addr.parse().map_err(|_| bad())?)),
+            #[cfg(unix)]
+            Some(("unix", path)) if !path.is_empty() => Ok(Addr::Unix(PathBuf::from(path))),
+            _ => Err(bad()),
+        }
+    }
+}
+
+impl Display for Addr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Addr::Tcp(addr) => write!(f, "tcp:{}", addr),
+            #[cfg(unix)]
+            Addr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    // The socket file is removed with the listener.
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    // Port 0 picks a free port, see local_addr.
+    // A socket file left behind by an earlier run is replaced.
+    pub fn bind(addr: &Addr) -> Result<Self> {
+        match addr {
+            Addr::Tcp(addr) => Ok(Listener::Tcp(TcpListener::bind(addr)?)),
+            #[cfg(unix)]
+            Addr::Unix(path) => {
+                match remove_file(path) {
+                    Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                    _ => {}
+                }
+                Ok(Listener::Unix(UnixListener::bind(path)?, path.clone()))
+            }
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<Addr> {
+        match self {
+            Listener::Tcp(l) => Ok(Addr::Tcp(l.local_addr()?)),
+            #[cfg(unix)]
+            Listener::Unix(_, path) => Ok(Addr::Unix(path.clone())),
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        match self {
+            Listener::Tcp(l) => l.set_nonblocking(nonblocking)?,
+            #[cfg(unix)]
+            Listener::Unix(l, _) => l.set_nonblocking(nonblocking)?,
+        }
+        Ok(())
+    }
+
+    // The stream blocks even if the listener does not.
+    pub fn accept(&self) -> io::Result<Stream> {
+        let stream = match self {
+            Listener::Tcp(l) => {
+                let (s, _) = l.accept()?;
+                s.set_nodelay(true)?;
+                Stream::Tcp(s)
+            }
+            #[cfg(unix)]
+            Listener::Unix(l, _) => Stream::Unix(l.accept()?.0),
+        };
+        stream.set_nonblocking(false)?;
+        Ok(stream)
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Listener::Unix(_, path) = self {
+            let _ = remove_file(path);
+        }
+    }
+}
+
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    pub fn connect(addr: &Addr) -> Result<Self> {
+        match addr {
+            Addr::Tcp(addr) => {
+                let s = TcpStream::connect(addr)?;
+                s.set_nodelay(true)?;
+                Ok(Stream::Tcp(s))
+            }
+            #[cfg(unix)]
+            Addr::Unix(path) => Ok(Stream::Unix(UnixStream::connect(path)?)),
+        }
+    }
+
+    // Another handle to the same socket, to read on one thread
+    // and write on another.
+    pub fn try_clone(&self) -> Result<Self> {
+        match self {
+            Stream::Tcp(s) => Ok(Stream::Tcp(s.try_clone()?)),
+            #[cfg(unix)]
+            Stream::Unix(s) => Ok(Stream::Unix(s.try_clone()?)),
+        }
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.set_nonblocking(nonblocking),
+        }
+    }
+
+    // Wakes up a thread reading from another handle of the socket.
+    pub fn shutdown(&self) {
+        let _ = match self {
+            Stream::Tcp(s) => s.shutdown(Shutdown::Both),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.shutdown(Shutdown::Both),
+        };
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.flush(),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.flush(),
+        }
+    }
+}
+
+pub fn write_frame<W: Write>(w: &mut W, tag: u8, body: &[u8]) -> Result<()> {
+    w.write_all(&(body.len() as u32).to_be_bytes())?;
+    w.write_all(&[tag])?;
+    w.write_all(body)?;
+    Ok(())
+}
+
+// The tag and body of the next frame, None if the stream
+// ends before one starts.
+pub fn read_frame<R: Read>(r: &mut R) -> Result<Option<(u8, Vec<u8>)>> {
+    let mut header = [0; 5];
+    match r.read_exact(&mut header[..1]) {
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        res => res?,
+    }
+    r.read_exact(&mut header[1..])?;
+    let len = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+    if len > MAX_FRAME {
+        return Err(Error::Protocol(format!("frame of {} bytes", len)));
+    }
+    let mut body = vec![0; len];
+    r.read_exact(&mut body)?;
+    Ok(Some((header[4], body)))
+}
+
+// Reads the fields of a frame body in order.
+pub struct Fields<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Fields { bytes }
+    }
+
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            return Err(Error::Protocol("message cut short".to_string()));
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    // A string written by put_str.
+    pub fn str(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| Error::Protocol("string is not utf-8".to_string()))
+    }
+
+    pub fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.bytes)
+    }
+}
+
+pub fn put_str(out: &mut Vec<u8>, s: &str) {
+    out.extend((s.len() as u32).to_be_bytes());
+    out.extend(s.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn frame_round_trip() {
+        let mut bytes = vec![];
+        write_frame(&mut bytes, 3, b"abc").unwrap();
+        write_frame(&mut bytes, 4, b"").unwrap();
+        let mut r = Cursor::new(bytes);
+        assert_eq!(read_frame(&mut r).unwrap(), Some((3, b"abc".to_vec())));
+        assert_eq!(read_frame(&mut r).unwrap(), Some((4, vec![])));
+        assert_eq!(read_frame(&mut r).unwrap(), None);
+    }
+
+    #[test]
+    fn malformed_frames() {
+        let mut bytes = vec![];
+        write_frame(&mut bytes, 3, b"abc").unwrap();
+        for len in 1..bytes.len() {
+            assert!(
+                read_frame(&mut Cursor::new(&bytes[..len])).is_err(),
+                "{}",
+                len
+            );
+        }
+        let mut huge = ((MAX_FRAME + 1) as u32).to_be_bytes().to_vec();
+        huge.push(3);
+        assert!(matches!(
+            read_frame(&mut Cursor::new(huge)),
+            Err(Error::Protocol(_))
+        ));
+    }
+
+    #[test]
+    fn fields() {
+        let mut body = vec![1];
+        body.extend(2u32.to_be_bytes());
+        body.extend((-3i32).to_be_bytes());
+        body.extend(4u64.to_be_bytes());
+        put_str(&mut body, "five");
+        body.push(6);
+        let mut f = Fields::new(&body);
+        assert_eq!(f.u8().unwrap(), 1);
+        assert_eq!(f.u32().unwrap(), 2);
+        assert_eq!(f.i32().unwrap(), -3);
+        assert_eq!(f.u64().unwrap(), 4);
+        assert_eq!(f.str().unwrap(), "five");
+        assert_eq!(f.rest(), &[6]);
+        assert!(matches!(f.u8(), Err(Error::Protocol(_))));
+
+        // a string longer than the body, and one that is not utf-8
+        let mut f = Fields::new(&[0, 0, 0, 9, b'a']);
+        assert!(f.str().is_err());
+        let mut f = Fields::new(&[0, 0, 0, 1, 0xff]);
+        assert!(f.str().is_err());
+    }
+
+    #[test]
+    fn addr_round_trip() {
+        for s in ["tcp:127.0.0.1:4000", "tcp:[::1]:0"] {
+            assert_eq!(s.parse::<Addr>().unwrap().to_string(), s);
+        }
+        #[cfg(unix)]
+        assert_eq!(
+            "unix:/tmp/astar.sock".parse::<Addr>().unwrap().to_string(),
+            "unix:/tmp/astar.sock"
+        );
+        for s in ["", "tcp:", "tcp:localhost", "unix:", "udp:1.2.3.4:5"] {
+            assert!(s.parse::<Addr>().is_err(), "{:?}", s);
+        }
+    }
+}
@@ -0,0 +1,499 @@
+// Pattern databases for the sliding puzzle.
+// A pattern is a set of tiles. Its database holds, for every placement of
+// the pattern tiles, the least cost of moving them to their goal cells when
+// only moves of pattern tiles are charged, so databases of disjoint
+// patterns can be added (see additive).
+// They are built by a parallel backward search over the placements of the
+// pattern tiles and the blank, one entry is the minimum over the blank cells.
+// Entries take 4 bits, costs from 15 on are stored as 15, which keeps
+// them admissible.
+use std::fmt::{Debug, Formatter};
+use std::fs::{create_dir_all, read, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::thread;
+
+use crate::error::{Error, Result};
+use crate::utils::combinators::Sum;
+use crate::utils::heuristic::Heuristic;
+use crate::utils::rank::{count_partial, rank_partial, unrank_partial};
+use crate::utils::structs::{Grid, TileCost};
+
+pub const MAX_ENTRY: u8 = 15;
+const UNSEEN: u8 = u8::MAX;
+const MAGIC: &[u8; 4] = b"PDB1";
+// Placements with the blank the default patterns may have, one byte each
+// while building.
+const DEFAULT_STATES: u64 = 1 << 27;
+
+#[derive(Clone)]
+pub struct Pdb {
+    rows: i32,
+    cols: i32,
+    cost: TileCost,
+    tiles: Vec<i32>,
+    // goal cells of the tiles, then of the blank
+    goal: Vec<u8>,
+    // slot[n] is the index of tile n in tiles
+    slot: Vec<Option<u8>>,
+    // two entries per byte, the even one in the low nibble
+    table: Vec<u8>,
+}
+
+impl Debug for Pdb {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Pdb {{ {}x{}, tiles: {:?}, entries: {} }}",
+            self.rows,
+            self.cols,
+            self.tiles,
+            self.entries()
+        )
+    }
+}
+
+impl Pdb {
+    // Checks the pattern and lays out an empty database.
+    fn layout(goal: &Grid, tiles: &[i32]) -> Result<Pdb> {
+        goal.validate()?;
+        let n = goal.data.len();
+        if goal.blanks().count() != 1 {
+            return Err(Error::BadPattern("the goal needs exactly one blank".into()));
+        }
+        if n > 64 {
+            return Err(Error::BadPattern(format!("{} cells, at most 64", n)));
+        }
+        if tiles.is_empty() {
+            return Err(Error::BadPattern("no tiles".into()));
+        }
+        if (1..tiles.len()).any(|i| tiles[..i].contains(&tiles[i])) {
+            return Err(Error::BadPattern(format!("{:?} has duplicates", tiles)));
+        }
+        if count_partial(n, tiles.len() + 1) > u32::MAX as u64 {
+            return Err(Error::BadPattern(format!(
+                "{} tiles are too many",
+                tiles.len()
+            )));
+        }
+
+        let mut slot = vec![None; n];
+        let mut cells = vec![None; tiles.len()];
+        for (i, cell) in goal.data.iter().enumerate() {
+            if let Some(t) = *cell {
+                if let Some(j) = tiles.iter().position(|&x| x == t) {
+                    slot[t as usize] = Some(j as u8);
+                    cells[j] = Some(i as u8);
+                }
+            }
+        }
+        let mut goal_cells = cells
+            .into_iter()
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| Error::BadPattern(format!("{:?} are not all tiles", tiles)))?;
+        goal_cells.push(goal.empty_idx as u8);
+        Ok(Pdb {
+            rows: goal.rows,
+            cols: goal.cols,
+            cost: goal.cost,
+            tiles: tiles.to_vec(),
+            goal: goal_cells,
+            slot,
+            table: vec![],
+        })
+    }
+
+    pub fn build(goal: &Grid, tiles: &[i32], threads: usize) -> Result<Pdb> {
+        let mut pdb = Pdb::layout(goal, tiles)?;
+        let dist = pdb.search(threads.max(1));
+
+        // Entries of one placement of the tiles are next to each other,
+        // the blank is the last digit of the rank.
+        let block = pdb.cells() - tiles.len();
+        let entries = count_partial(pdb.cells(), tiles.len()) as usize;
+        pdb.table = vec![0; entries.div_ceil(2)];
+        for (i, placements) in dist.chunks(block).enumerate() {
+            let d = placements
+                .iter()
+                .map(|d| d.load(Ordering::Relaxed))
+                .min()
+                .unwrap_or(UNSEEN)
+                .min(MAX_ENTRY);
+            pdb.table[i / 2] |= d << (4 * (i % 2));
+        }
+        Ok(pdb)
+    }
+
+    // Backward search from the goal placement in cost buckets. The threads
+    // expand one bucket at a time and lower the cost of a placement with
+    // fetch_min, the one that lowers it queues it.
+    fn search(&self, threads: usize) -> Vec<AtomicU8> {
+        let n = self.cells();
+        let size = count_partial(n, self.goal.len());
+        let dist: Vec<AtomicU8> = (0..size).map(|_| AtomicU8::new(UNSEEN)).collect();
+        let start = rank_partial(&self.goal, n) as u32;
+        dist[start as usize].store(0, Ordering::Relaxed);
+        let mut buckets: Vec<Vec<u32>> = vec![vec![]; MAX_ENTRY as usize];
+        buckets[0].push(start);
+
+        for d in 0..MAX_ENTRY {
+            // Moves of other tiles are free and stay in the bucket.
+            let mut frontier = std::mem::take(&mut buckets[d as usize]);
+            while !frontier.is_empty() {
+                let chunk = frontier.len().div_ceil(threads);
+                let found: Vec<Vec<Vec<u32>>> = thread::scope(|s| {
+                    let handles: Vec<_> = frontier
+                        .chunks(chunk)
+                        .map(|part| s.spawn(|| self.expand(part, d, &dist)))
+                        .collect();
+                    handles.into_iter().map(|h| h.join().unwrap()).collect()
+                });
+                frontier = vec![];
+                for local in found {
+                    for (nd, ranks) in local.into_iter().enumerate() {
+                        if nd == d as usize {
+                            frontier.extend(ranks);
+                        } else {
+                            buckets[nd].extend(ranks);
+                        }
+                    }
+                }
+            }
+        }
+        dist
+    }
+
+    fn expand(&self, part: &[u32], d: u8, dist: &[AtomicU8]) -> Vec<Vec<u32>> {
+        let n = self.cells();
+        let k = self.tiles.len();
+        let mut found = vec![vec![]; MAX_ENTRY as usize];
+        let mut seq = vec![0; k + 1];
+        let mut next = vec![0; k + 1];
+        for &r in part {
+            if dist[r as usize].load(Ordering::Relaxed) != d {
+                continue;
+            }
+            unrank_partial(r as u64, n, &mut seq);
+            let blank = seq[k] as i32;
+            let (x, y) = (blank / self.cols, blank % self.cols);
+            for (dx, dy) in [(0, 1), (0, -1), (1, 0), (-1, 0)] {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || nx >= self.rows || ny < 0 || ny >= self.cols {
+                    continue;
+                }
+                let q = (nx * self.cols + ny) as u8;
+                next.copy_from_slice(&seq);
+                next[k] = q;
+                let mut c = 0;
+                if let Some(j) = seq[..k].iter().position(|&p| p == q) {
+                    next[j] = blank as u8;
+                    c = self.cost.of(self.tiles[j]);
+                }
+                let nd = (d as i32 + c).min(MAX_ENTRY as i32) as u8;
+                let nr = rank_partial(&next, n) as u32;
+                let old = dist[nr as usize].fetch_min(nd, Ordering::Relaxed);
+                if old > nd && nd < MAX_ENTRY {
+                    found[nd as usize].push(nr);
+                }
+            }
+        }
+        found
+    }
+
+    fn cells(&self) -> usize {
+        (self.rows * self.cols) as usize
+    }
+
+    pub fn tiles(&self) -> &[i32] {
+        &self.tiles
+    }
+
+    pub fn entries(&self) -> u64 {
+        count_partial(self.cells(), self.tiles.len())
+    }
+
+    fn entry(&self, i: u64) -> u8 {
+        (self.table[i as usize / 2] >> (4 * (i % 2))) & 0xf
+    }
+
+    // True if the database was built for this goal and pattern.
+    pub fn matches(&self, goal: &Grid, tiles: &[i32]) -> bool {
+        match Pdb::layout(goal, tiles) {
+            Ok(other) => {
+                (self.rows, self.cols, self.cost) == (other.rows, other.cols, other.cost)
+                    && self.tiles == other.tiles
+                    && self.goal == other.goal
+            }
+            Err(_) => false,
+        }
+    }
+
+    // The layout is the header fields in order, little endian, then the
+    // table and an FNV-1a checksum of everything before it.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut buf = MAGIC.to_vec();
+        buf.extend(self.rows.to_le_bytes());
+        buf.extend(self.cols.to_le_bytes());
+        buf.push(match self.cost {
+            TileCost::Unit => 0,
+            TileCost::Heavy => 1,
+        });
+        buf.extend((self.tiles.len() as u32).to_le_bytes());
+        for t in &self.tiles {
+            buf.extend(t.to_le_bytes());
+        }
+        buf.extend(&self.goal);
+        buf.extend((self.table.len() as u64).to_le_bytes());
+        buf.extend(&self.table);
+        buf.extend(fnv1a(&buf).to_le_bytes());
+        File::create(path)?.write_all(&buf)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Pdb> {
+        let name = path.as_ref().display().to_string();
+        let corrupt = |msg: &str| Error::Corrupt(format!("{}: {}", name, msg));
+        let buf = read(&path)?;
+        if buf.len() < MAGIC.len() + 8 || &buf[..MAGIC.len()] != MAGIC {
+            return Err(corrupt("not a pattern database"));
+        }
+        let (body, sum) = buf.split_at(buf.len() - 8);
+        if fnv1a(body).to_le_bytes() != sum {
+            return Err(corrupt("checksum mismatch"));
+        }
+
+        let mut r = Reader {
+            buf: &body[MAGIC.len()..],
+        };
+        let short = || corrupt("truncated");
+        let rows = i32::from_le_bytes(r.take(4).ok_or_else(short)?.try_into().unwrap());
+        let cols = i32::from_le_bytes(r.take(4).ok_or_else(short)?.try_into().unwrap());
+        let cost = match r.take(1).ok_or_else(short)?[0] {
+            0 => TileCost::Unit,
+            1 => TileCost::Heavy,
+            _ => return Err(corrupt("unknown tile cost")),
+        };
+        let k = u32::from_le_bytes(r.take(4).ok_or_else(short)?.try_into().unwrap()) as usize;
+        // rows and cols come from the file, the product may overflow.
+        let n = match (rows as usize).checked_mul(cols as usize) {
+            Some(n) if rows >= 1 && cols >= 1 && n <= 64 && k != 0 && k < n => n,
+            _ => return Err(corrupt("bad dimensions")),
+        };
+        let tiles: Vec<i32> = r
+            .take(4 * k)
+            .ok_or_else(short)?
+            .chunks(4)
+            .map(|b| i32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        let goal = r.take(k + 1).ok_or_else(short)?.to_vec();
+        if goal.iter().any(|&c| c as usize >= n) {
+            return Err(corrupt("bad goal cells"));
+        }
+        let len = u64::from_le_bytes(r.take(8).ok_or_else(short)?.try_into().unwrap());
+        let table = r.take(len as usize).ok_or_else(short)?.to_vec();
+
+        let mut slot = vec![None; n];
+        for (j, &t) in tiles.iter().enumerate() {
+            if t < 0 || t as usize >= n || slot[t as usize].is_some() {
+                return Err(corrupt("bad tiles"));
+            }
+            slot[t as usize] = Some(j as u8);
+        }
+        let pdb = Pdb {
+            rows,
+            cols,
+            cost,
+            tiles,
+            goal,
+            slot,
+            table,
+        };
+        if !r.buf.is_empty() || pdb.table.len() as u64 != pdb.entries().div_ceil(2) {
+            return Err(corrupt("bad table size"));
+        }
+        Ok(pdb)
+    }
+
+    // Loads the database from dir if it holds one for this goal and
+    // pattern, otherwise builds it and saves it there.
+    pub fn cached<P: AsRef<Path>>(
+        dir: P,
+        goal: &Grid,
+        tiles: &[i32],
+        threads: usize,
+    ) -> Result<Pdb> {
+        let path = cache_path(dir.as_ref(), goal, tiles);
+        if let Ok(pdb) = Pdb::load(&path) {
+            if pdb.matches(goal, tiles) {
+                return Ok(pdb);
+            }
+        }
+        let pdb = Pdb::build(goal, tiles, threads)?;
+        create_dir_all(dir)?;
+        pdb.save(&path)?;
+        Ok(pdb)
+    }
+}
+
+impl Heuristic<Grid> for Pdb {
+    fn h(&self, state: &Grid, _: &Grid) -> i32 {
+        let mut seq = [0; 64];
+        for (i, cell) in state.data.iter().enumerate() {
+            if let Some(t) = *cell {
+                if let Some(j) = self.slot[t as usize] {
+                    seq[j as usize] = i as u8;
+                }
+            }
+        }
+        let rank = rank_partial(&seq[..self.tiles.len()], self.cells());
+        self.entry(rank) as i32
+    }
+}
+
+// The sum of databases of disjoint patterns.
+pub fn additive(pdbs: Vec<Pdb>) -> Sum<Grid> {
+    pdbs.into_iter().fold(Sum::new(), |sum, pdb| sum.with(pdb))
+}
+
+// Tiles in the order of their goal cells, split into patterns of equal
+// size, as large as building them in about 128MB allows.
+pub fn default_patterns(goal: &Grid) -> Vec<Vec<i32>> {
+    let n = goal.data.len();
+    let tiles: Vec<i32> = goal.data.iter().flatten().copied().collect();
+    let mut k = 1;
+    while k < 8 && k < tiles.len() && count_partial(n, k + 2) <= DEFAULT_STATES {
+        k += 1;
+    }
+    let groups = tiles.len().div_ceil(k).max(1);
+    let size = tiles.len().div_ceil(groups);
+    tiles.chunks(size.max(1)).map(|c| c.to_vec()).collect()
+}
+
+// Additive databases of the default patterns, cached in dir.
+pub fn default_pdbs<P: AsRef<Path>>(dir: P, goal: &Grid, threads: usize) -> Result<Vec<Pdb>> {
+    default_patterns(goal)
+        .iter()
+        .map(|tiles| Pdb::cached(dir.as_ref(), goal, tiles, threads))
+        .collect()
+}
+
+fn cache_path(dir: &Path, goal: &Grid, tiles: &[i32]) -> PathBuf {
+    let mut key = format!("{:?} {:?}", goal.cost, goal.data).into_bytes();
+    key.extend(format!(" {:?}", tiles).bytes());
+    dir.join(format!(
+        "pdb_{}x{}_{:016x}.bin",
+        goal.rows,
+        goal.cols,
+        fnv1a(&key)
+    ))
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < n {
+            return None;
+        }
+        let (head, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Some(head)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::bfs::enumerate;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("astar-pdb-{}-{}.bin", std::process::id(), name))
+    }
+
+    fn goal_and_patterns() -> (Grid, Vec<Vec<i32>>) {
+        let goal = Grid::rand_rect_with_seed(3, 3, 0);
+        let tiles: Vec<i32> = goal.data.iter().flatten().copied().collect();
+        (goal, tiles.chunks(4).map(|c| c.to_vec()).collect())
+    }
+
+    #[test]
+    fn admissible_on_3x3() {
+        let (goal, patterns) = goal_and_patterns();
+        let space = enumerate(&goal, 2).unwrap();
+        let pdbs: Vec<Pdb> = patterns
+            .iter()
+            .map(|tiles| Pdb::build(&goal, tiles, 2).unwrap())
+            .collect();
+        let sum = additive(pdbs.clone());
+        assert_eq!(sum.h(&goal, &goal), 0);
+        for seed in 0..200 {
+            let state = goal.rand_actions_with_seed(60, seed);
+            let d = space.distance(&state).unwrap();
+            assert!(sum.h(&state, &goal) <= d, "{}", state);
+            for pdb in pdbs.iter() {
+                assert!(pdb.h(&state, &goal) <= d);
+            }
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let (goal, patterns) = goal_and_patterns();
+        let pdb = Pdb::build(&goal, &patterns[0], 2).unwrap();
+        let path = temp_path("save");
+        pdb.save(&path).unwrap();
+        let loaded = Pdb::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.matches(&goal, &patterns[0]));
+        assert_eq!(loaded.table, pdb.table);
+    }
+
+    #[test]
+    fn checksum_rejects_changes() {
+        let (goal, patterns) = goal_and_patterns();
+        let pdb = Pdb::build(&goal, &patterns[0], 2).unwrap();
+        let path = temp_path("checksum");
+        pdb.save(&path).unwrap();
+        let buf = read(&path).unwrap();
+        for i in [MAGIC.len(), buf.len() / 2, buf.len() - 9, buf.len() - 1] {
+            let mut bad = buf.clone();
+            bad[i] ^= 1;
+            File::create(&path).unwrap().write_all(&bad).unwrap();
+            assert!(
+                matches!(Pdb::load(&path), Err(Error::Corrupt(_))),
+                "byte {}",
+                i
+            );
+        }
+        File::create(&path)
+            .unwrap()
+            .write_all(&buf[..buf.len() - 3])
+            .unwrap();
+        assert!(matches!(Pdb::load(&path), Err(Error::Corrupt(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn huge_dimensions() {
+        let mut buf = MAGIC.to_vec();
+        buf.extend(i32::MAX.to_le_bytes());
+        buf.extend(i32::MAX.to_le_bytes());
+        buf.push(0);
+        buf.extend(1u32.to_le_bytes());
+        buf.extend(fnv1a(&buf).to_le_bytes());
+        let path = temp_path("huge");
+        File::create(&path).unwrap().write_all(&buf).unwrap();
+        let res = Pdb::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(res, Err(Error::Corrupt(_))));
+    }
+}
@@ -0,0 +1,273 @@
+// Perfect ranking of permutations and partial permutations: sequences
+// of k distinct values out of 0..n, n <= 64, numbered 0..count_partial(n, k).
+// Two schemes:
+// - lexicographic: ranks follow the order of the sequences. Digit i of a
+//   sequence is the number of unused values below seq[i], the rank reads
+//   the digits in the mixed radix n, n - 1, ..., n - k + 1. Sequences with
+//   a common prefix get neighbouring ranks, pattern databases rely on that.
+// - Myrvold-Ruskey: ranks follow no useful order, but need no bitmask
+//   counting, a rank or unrank is k swaps. A partial sequence is read as
+//   the last k cells of a permutation, seq[0] in the last cell.
+// Ranks are u64, so a full permutation has at most 20 values.
+use crate::error::{Error, Result};
+use crate::utils::structs::Grid;
+
+// Full permutations of at most this many values fit in a rank.
+pub const MAX_PERM: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ranking {
+    Lex,
+    MyrvoldRuskey,
+}
+
+impl Ranking {
+    pub fn rank_partial(&self, seq: &[u8], n: usize) -> u64 {
+        match self {
+            Ranking::Lex => rank_partial(seq, n),
+            Ranking::MyrvoldRuskey => mr_rank_partial(seq, n),
+        }
+    }
+
+    pub fn unrank_partial(&self, rank: u64, n: usize, seq: &mut [u8]) {
+        match self {
+            Ranking::Lex => unrank_partial(rank, n, seq),
+            Ranking::MyrvoldRuskey => mr_unrank_partial(rank, n, seq),
+        }
+    }
+
+    pub fn rank(&self, perm: &[u8]) -> u64 {
+        match self {
+            Ranking::Lex => rank_partial(perm, perm.len()),
+            Ranking::MyrvoldRuskey => mr_rank(perm),
+        }
+    }
+
+    pub fn unrank(&self, rank: u64, perm: &mut [u8]) {
+        match self {
+            Ranking::Lex => unrank_partial(rank, perm.len(), perm),
+            Ranking::MyrvoldRuskey => mr_unrank(rank, perm),
+        }
+    }
+}
+
+// n! / (n - k)!
+pub fn count_partial(n: usize, k: usize) -> u64 {
+    (n - k + 1..=n).map(|x| x as u64).product()
+}
+
+pub fn rank_partial(seq: &[u8], n: usize) -> u64 {
+    let mut used = 0u64;
+    let mut rank = 0;
+    for (i, &v) in seq.iter().enumerate() {
+        let below = (used & ((1u64 << v) - 1)).count_ones() as u64;
+        rank = rank * (n - i) as u64 + v as u64 - below;
+        used |= 1 << v;
+    }
+    rank
+}
+
+// Writes the sequence of the given rank to seq, k = seq.len().
+pub fn unrank_partial(mut rank: u64, n: usize, seq: &mut [u8]) {
+    let k = seq.len();
+    for i in (0..k).rev() {
+        let base = (n - i) as u64;
+        seq[i] = (rank % base) as u8;
+        rank /= base;
+    }
+    let mut used = 0u64;
+    for v in seq.iter_mut() {
+        // the digit-th value not used yet
+        let mut digit = *v;
+        let mut x = 0;
+        loop {
+            if used & (1 << x) == 0 {
+                if digit == 0 {
+                    break;
+                }
+                digit -= 1;
+            }
+            x += 1;
+        }
+        *v = x;
+        used |= 1 << x;
+    }
+}
+
+pub fn mr_rank(perm: &[u8]) -> u64 {
+    let n = perm.len();
+    let mut p = [0; 64];
+    let mut inv = [0; 64];
+    for (i, &v) in perm.iter().enumerate() {
+        p[i] = v;
+        inv[v as usize] = i as u8;
+    }
+    mr_rank_tail(&mut p, &mut inv, n, n)
+}
+
+pub fn mr_unrank(mut rank: u64, perm: &mut [u8]) {
+    let n = perm.len();
+    for (i, v) in perm.iter_mut().enumerate() {
+        *v = i as u8;
+    }
+    for i in (1..=n).rev() {
+        perm.swap(i - 1, (rank % i as u64) as usize);
+        rank /= i as u64;
+    }
+}
+
+pub fn mr_rank_partial(seq: &[u8], n: usize) -> u64 {
+    let mut p = [0; 64];
+    let mut inv = [u8::MAX; 64];
+    for (j, &v) in seq.iter().enumerate() {
+        p[n - 1 - j] = v;
+        inv[v as usize] = (n - 1 - j) as u8;
+    }
+    // The values left out go to the front in any order,
+    // the rank does not depend on them.
+    let mut free = 0;
+    for (v, cell) in inv.iter_mut().enumerate().take(n) {
+        if *cell == u8::MAX {
+            p[free] = v as u8;
+            *cell = free as u8;
+            free += 1;
+        }
+    }
+    mr_rank_tail(&mut p, &mut inv, n, seq.len())
+}
+
+pub fn mr_unrank_partial(mut rank: u64, n: usize, seq: &mut [u8]) {
+    let mut p = [0; 64];
+    for (i, v) in p.iter_mut().enumerate().take(n) {
+        *v = i as u8;
+    }
+    for i in (n - seq.len() + 1..=n).rev() {
+        p.swap(i - 1, (rank % i as u64) as usize);
+        rank /= i as u64;
+    }
+    for (j, v) in seq.iter_mut().enumerate() {
+        *v = p[n - 1 - j];
+    }
+}
+
+// The first k steps of the Myrvold-Ruskey rank, each moves value i - 1
+// to cell i - 1 and records what was there.
+fn mr_rank_tail(p: &mut [u8; 64], inv: &mut [u8; 64], n: usize, k: usize) -> u64 {
+    let mut digits = [0; 64];
+    for (j, digit) in digits.iter_mut().enumerate().take(k) {
+        let i = n - j;
+        let s = p[i - 1];
+        *digit = s;
+        p.swap(i - 1, inv[i - 1] as usize);
+        inv.swap(s as usize, i - 1);
+    }
+    (0..k)
+        .rev()
+        .fold(0, |rank, j| rank * (n - j) as u64 + digits[j] as u64)
+}
+
+// The cells of a board with one blank as a permutation,
+// cell i holds its tile or rows * cols - 1 for the blank.
+pub fn grid_perm(grid: &Grid) -> Result<Vec<u8>> {
+    let n = grid.data.len();
+    if grid.blanks().count() != 1 {
+        return Err(Error::NotPermutation("ranks need one blank".to_string()));
+    }
+    if n > 64 {
+        return Err(Error::TooLarge(format!("{} cells, at most 64", n)));
+    }
+    Ok(grid
+        .data
+        .iter()
+        .map(|cell| cell.unwrap_or(n as i32 - 1) as u8)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RANKINGS: [Ranking; 2] = [Ranking::Lex, Ranking::MyrvoldRuskey];
+
+    // Every rank of 0..count_partial(n, k) unranks to a distinct
+    // sequence of distinct values, which ranks back to it.
+    fn check_partial(ranking: Ranking, n: usize, k: usize) {
+        let mut seen = std::collections::HashSet::new();
+        let mut seq = vec![0; k];
+        for rank in 0..count_partial(n, k) {
+            ranking.unrank_partial(rank, n, &mut seq);
+            let mut used = 0u64;
+            for &v in seq.iter() {
+                assert!((v as usize) < n && used & (1 << v) == 0, "{:?}", seq);
+                used |= 1 << v;
+            }
+            assert_eq!(
+                ranking.rank_partial(&seq, n),
+                rank,
+                "{:?} {:?}",
+                ranking,
+                seq
+            );
+            assert!(seen.insert(seq.clone()));
+        }
+    }
+
+    #[test]
+    fn partial_round_trip() {
+        for ranking in RANKINGS {
+            for n in 1..=7 {
+                for k in 0..=n {
+                    check_partial(ranking, n, k);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn full_round_trip() {
+        for ranking in RANKINGS {
+            for n in 1..=7 {
+                let mut perm = vec![0; n];
+                for rank in 0..count_partial(n, n) {
+                    ranking.unrank(rank, &mut perm);
+                    assert_eq!(ranking.rank(&perm), rank);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lex_follows_order() {
+        let mut prev = vec![0; 4];
+        let mut seq = vec![0; 4];
+        for rank in 1..count_partial(7, 4) {
+            unrank_partial(rank - 1, 7, &mut prev);
+            unrank_partial(rank, 7, &mut seq);
+            assert!(prev < seq);
+        }
+    }
+
+    #[test]
+    fn largest_permutation() {
+        let last = count_partial(MAX_PERM, MAX_PERM) - 1;
+        for ranking in RANKINGS {
+            let mut perm = [0; MAX_PERM];
+            ranking.unrank(last, &mut perm);
+            assert_eq!(ranking.rank(&perm), last);
+        }
+        let reversed: Vec<u8> = (0..MAX_PERM as u8).rev().collect();
+        assert_eq!(rank_partial(&reversed, MAX_PERM), last);
+    }
+
+    #[test]
+    fn grid_round_trip() {
+        for ranking in RANKINGS {
+            for seed in 0..20 {
+                let grid = Grid::rand_rect_with_seed(3, 4, seed);
+                let rank = grid.rank(ranking).unwrap();
+                assert_eq!(Grid::unrank(rank, 3, 4, ranking).unwrap(), grid);
+            }
+            assert!(Grid::unrank(count_partial(12, 12), 3, 4, ranking).is_err());
+        }
+    }
+}
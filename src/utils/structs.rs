@@ -1,39 +1,438 @@
-#![allow(dead_code)]
-#![allow(unused_variables)]
-#![allow(unused_imports)]
+use crate::error::{Error, Result};
+use crate::utils::cost::Cost;
+use crate::utils::heuristic::Heuristic;
+use crate::utils::rank::{count_partial, grid_perm, Ranking, MAX_PERM};
+use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fmt::{Display, Formatter};
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::Hash;
+use std::ops::BitXor;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone, Hash, Serialize, Deserialize)]
+// Moves of a blank, a move and its opposite differ in the last bit.
+const MOVES: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+// Bytes that Grid::pack writes for a board of len cells.
+fn packed_len(len: usize) -> usize {
+    if len <= 16 {
+        len.div_ceil(2)
+    } else {
+        len
+    }
+}
+
+// A rows x cols board stored row by row. Blanks are None, a board
+// with k blanks holds the tiles 0..rows * cols - k and empty_idx is
+// the index of the first blank.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "GridRepr")]
 pub struct Grid {
     pub data: Vec<Option<i32>>,
-    pub size: i32,
+    pub rows: i32,
+    pub cols: i32,
     pub empty_idx: i32,
+    pub cost: TileCost,
+}
+
+// What moving a tile costs.
+#[derive(Debug, Clone, Copy, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TileCost {
+    // Every move costs 1.
+    #[default]
+    Unit,
+    // Moving tile n costs n + 1, the heavy tile puzzle.
+    Heavy,
+}
+
+impl TileCost {
+    pub fn of(&self, tile: i32) -> i32 {
+        match self {
+            TileCost::Unit => 1,
+            TileCost::Heavy => tile + 1,
+        }
+    }
 }
 
-// The state trait makes the framework generic.
-// For later.
-// pub trait State {
-//     fn expand(&self) -> Vec<Self>;
-// }
+// Grids were written with a single size before boards could be
+// rectangular, such files are still read as size x size boards.
+#[derive(Deserialize)]
+struct GridRepr {
+    data: Vec<Option<i32>>,
+    #[serde(default)]
+    size: i32,
+    #[serde(default)]
+    rows: i32,
+    #[serde(default)]
+    cols: i32,
+    empty_idx: i32,
+    #[serde(default)]
+    cost: TileCost,
+}
+
+impl From<GridRepr> for Grid {
+    fn from(g: GridRepr) -> Self {
+        let (rows, cols) = if g.rows > 0 || g.cols > 0 {
+            (g.rows, g.cols)
+        } else {
+            (g.size, g.size)
+        };
+        Grid::new_rect(g.data, rows, cols, g.empty_idx).with_cost(g.cost)
+    }
+}
+
+// The state trait makes the framework generic,
+// every search runs on any type implementing it.
+pub trait State: Clone + Eq + Hash + Debug + Send + Sync + 'static {
+    type Cost: Cost;
+    // Zobrist hasher for HDA* and its abstract variant for ZHDA*.
+    type Hasher: StateHasher<Self>;
+    type AbstractHasher: StateHasher<Self>;
+
+    // Successor states with the cost of the move.
+    fn successors(&self) -> Vec<(Self, Self::Cost)>;
+    // successors() with an id below 64 for each move and the id of the
+    // move that leads back, if any, frontier search marks used moves in
+    // a u64. The default numbers the successors and looks for the way
+    // back by expanding them.
+    fn moves(&self) -> Vec<(Self, Self::Cost, u8, Option<u8>)> {
+        numbered_moves(self)
+    }
+    // Check that searching from self to end is well defined.
+    fn check_pair(&self, end: &Self) -> Result<()>;
+    fn hasher(&self) -> Self::Hasher;
+    fn abstract_hasher(&self) -> Self::AbstractHasher;
+    // hasher() and abstract_hasher() with the given keys, for states
+    // whose hashers have a choice of keys. The default ignores keys.
+    fn keyed_hasher(&self, _keys: Keys) -> AnyHasher<Self> {
+        AnyHasher::new(self.hasher())
+    }
+    fn keyed_abstract_hasher(&self, _keys: Keys) -> AnyHasher<Self> {
+        AnyHasher::new(self.abstract_hasher())
+    }
+    // Fixed length encoding for the external search, the states of one
+    // search all have the same length and equal states equal bytes.
+    fn pack(&self, out: &mut Vec<u8>);
+    // Decode a packed state, like is any state of the same search.
+    fn unpack(bytes: &[u8], like: &Self) -> Self;
+}
+
+fn numbered_moves<S: State>(state: &S) -> Vec<(S, S::Cost, u8, Option<u8>)> {
+    state
+        .successors()
+        .into_iter()
+        .enumerate()
+        .map(|(i, (s, c))| {
+            let back = s.successors().iter().position(|(t, _)| t == state);
+            (s, c, i as u8, back.map(|b| b as u8))
+        })
+        .collect()
+}
+
+// Hashes a whole state, HDA* sends a state to thread hash % num_threads.
+pub trait StateHasher<S>: Clone + Send + Sync + 'static {
+    fn hash(&self, state: &S) -> u64;
+    // Successors of a state with the cost of the move and their hash,
+    // given the hash of the state. Hashers that can update a hash
+    // override it, the default hashes every successor.
+    fn hashed_successors(&self, state: &S, _hash: u64) -> Vec<(S, S::Cost, u64)>
+    where
+        S: State,
+    {
+        state
+            .successors()
+            .into_iter()
+            .map(|(s, c)| {
+                let hash = self.hash(&s);
+                (s, c, hash)
+            })
+            .collect()
+    }
+
+    // The hash of succ, a successor of state, given the hash of state.
+    // Hashers that can update a hash override it.
+    fn successor_hash(&self, _state: &S, _hash: u64, succ: &S) -> u64 {
+        self.hash(succ)
+    }
+}
+
+impl State for Grid {
+    type Cost = i32;
+    type Hasher = ZHasher;
+    type AbstractHasher = AZHasher;
+
+    fn successors(&self) -> Vec<(Self, i32)> {
+        self.expand_with_cost()
+    }
+
+    // Move 4 * k + d moves the k-th blank in direction d of MOVES,
+    // the way back moves the same blank in the opposite direction.
+    fn moves(&self) -> Vec<(Self, i32, u8, Option<u8>)> {
+        if self.blanks().count() > 16 {
+            return numbered_moves(self);
+        }
+        let mut moves = Vec::with_capacity(4);
+        for (k, blank) in self.blanks().enumerate() {
+            for (d, &action) in MOVES.iter().enumerate() {
+                if let Ok((grid, cost)) = self.do_move_with_cost(blank, action) {
+                    let target = self.target(blank, action).unwrap();
+                    let rank = grid.blanks().position(|b| b == target).unwrap();
+                    let back = 4 * rank + (d ^ 1);
+                    moves.push((grid, cost, (4 * k + d) as u8, Some(back as u8)));
+                }
+            }
+        }
+        moves
+    }
+
+    fn check_pair(&self, end: &Self) -> Result<()> {
+        Grid::check_pair(self, end)
+    }
+
+    fn hasher(&self) -> ZHasher {
+        ZHasher::new(self.rows, self.cols)
+    }
+
+    fn abstract_hasher(&self) -> AZHasher {
+        AZHasher::new(self.rows, self.cols)
+    }
+
+    fn keyed_hasher(&self, keys: Keys) -> AnyHasher<Self> {
+        let (rows, cols, seed) = (self.rows, self.cols, keys.seed.unwrap_or(Z_SEED));
+        match keys.width {
+            KeyWidth::Bits32 => AnyHasher::new(ZHasher::<u32>::with_seed(rows, cols, seed)),
+            KeyWidth::Bits64 => AnyHasher::new(ZHasher::<u64>::with_seed(rows, cols, seed)),
+            KeyWidth::Bits128 => AnyHasher::new(ZHasher::<u128>::with_seed(rows, cols, seed)),
+        }
+    }
+
+    fn keyed_abstract_hasher(&self, keys: Keys) -> AnyHasher<Self> {
+        let (rows, cols, seed) = (self.rows, self.cols, keys.seed.unwrap_or(AZ_SEED));
+        match keys.width {
+            KeyWidth::Bits32 => AnyHasher::new(AZHasher::<u32>::with_seed(rows, cols, seed)),
+            KeyWidth::Bits64 => AnyHasher::new(AZHasher::<u64>::with_seed(rows, cols, seed)),
+            KeyWidth::Bits128 => AnyHasher::new(AZHasher::<u128>::with_seed(rows, cols, seed)),
+        }
+    }
+
+    // Two cells per byte up to 16 cells, one above, blanks are all ones.
+    fn pack(&self, out: &mut Vec<u8>) {
+        let code = |cell: &Option<i32>| cell.map_or(u8::MAX, |t| t as u8);
+        if self.data.len() <= 16 {
+            for pair in self.data.chunks(2) {
+                let hi = pair.get(1).map_or(0xf, code) & 0xf;
+                out.push(code(&pair[0]) & 0xf | hi << 4);
+            }
+        } else {
+            out.extend(self.data.iter().map(code));
+        }
+    }
+
+    fn unpack(bytes: &[u8], like: &Self) -> Self {
+        let len = like.data.len();
+        let codes: Vec<u8> = if len <= 16 {
+            bytes
+                .iter()
+                .flat_map(|b| [b & 0xf, b >> 4])
+                .take(len)
+                .collect()
+        } else {
+            bytes.to_vec()
+        };
+        let blank = if len <= 16 { 0xf } else { u8::MAX };
+        let data = codes
+            .iter()
+            .map(|&c| (c != blank).then_some(c as i32))
+            .collect();
+        let mut grid = Grid::new_rect(data, like.rows, like.cols, 0).with_cost(like.cost);
+        grid.empty_idx = grid.first_blank();
+        grid
+    }
+}
 
 impl Grid {
+    // A size x size board.
+    // Trusts the caller, use try_new for data from files or users.
     pub fn new(nums: Vec<Option<i32>>, size: i32, empty_idx: i32) -> Self {
+        Grid::new_rect(nums, size, size, empty_idx)
+    }
+
+    pub fn new_rect(nums: Vec<Option<i32>>, rows: i32, cols: i32, empty_idx: i32) -> Self {
         Grid {
             data: nums,
-            size,
+            rows,
+            cols,
             empty_idx,
+            cost: TileCost::Unit,
+        }
+    }
+
+    pub fn with_cost(mut self, cost: TileCost) -> Self {
+        self.cost = cost;
+        self
+    }
+
+    // The same board with the n - 1 highest tiles turned into blanks,
+    // apply it to both start and end of an instance.
+    pub fn with_blanks(mut self, n: i32) -> Self {
+        let num_tiles = self.len() - n;
+        for cell in self.data.iter_mut() {
+            if cell.is_some_and(|t| t >= num_tiles) {
+                *cell = None;
+            }
+        }
+        self.empty_idx = self.first_blank();
+        self
+    }
+
+    pub fn blanks(&self) -> impl Iterator<Item = i32> + '_ {
+        (0..self.len()).filter(|&i| self.data[i as usize].is_none())
+    }
+
+    fn first_blank(&self) -> i32 {
+        self.blanks().next().unwrap_or(-1)
+    }
+
+    pub fn try_new(nums: Vec<Option<i32>>, size: i32, empty_idx: i32) -> Result<Self> {
+        Grid::try_new_rect(nums, size, size, empty_idx)
+    }
+
+    pub fn try_new_rect(
+        nums: Vec<Option<i32>>,
+        rows: i32,
+        cols: i32,
+        empty_idx: i32,
+    ) -> Result<Self> {
+        let grid = Grid::new_rect(nums, rows, cols, empty_idx);
+        grid.validate()?;
+        Ok(grid)
+    }
+
+    // Encoding that needs no other grid to decode, for messages between
+    // processes: rows and cols as u16, the tile cost, then the cells
+    // as pack writes them.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.extend((self.rows as u16).to_be_bytes());
+        out.extend((self.cols as u16).to_be_bytes());
+        out.push(match self.cost {
+            TileCost::Unit => 0,
+            TileCost::Heavy => 1,
+        });
+        self.pack(out);
+    }
+
+    // Bytes that pack writes.
+    pub fn packed_len(&self) -> usize {
+        packed_len(self.data.len())
+    }
+
+    // Decode and validate an encoded grid, also returns the bytes used.
+    // The size comes from another process, it is checked against the
+    // bytes at hand before anything is allocated for it.
+    pub fn decode(bytes: &[u8]) -> Result<(Self, usize)> {
+        let short = || Error::Protocol("grid cut short".to_string());
+        let header = bytes.get(..5).ok_or_else(short)?;
+        let rows = u16::from_be_bytes([header[0], header[1]]) as usize;
+        let cols = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let cost = match header[4] {
+            0 => TileCost::Unit,
+            1 => TileCost::Heavy,
+            c => return Err(Error::Protocol(format!("unknown tile cost {}", c))),
+        };
+        let len = rows
+            .checked_mul(cols)
+            .filter(|&len| packed_len(len) <= bytes.len() - 5)
+            .ok_or_else(short)?;
+        let packed = packed_len(len);
+        let like = Grid::new_rect(vec![None; len], rows as i32, cols as i32, 0).with_cost(cost);
+        let grid = Grid::unpack(&bytes[5..5 + packed], &like);
+        grid.validate()?;
+        Ok((grid, 5 + packed))
+    }
+
+    // Number of cells.
+    pub fn len(&self) -> i32 {
+        self.rows * self.cols
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Check that data holds rows * cols cells, k >= 1 blanks, the tiles
+    // 0..rows * cols - k exactly once and that empty_idx is the first blank.
+    pub fn validate(&self) -> Result<()> {
+        let len = self.len().max(0) as usize;
+        if self.rows < 1 || self.cols < 1 || self.data.len() != len {
+            return Err(Error::BadLength {
+                expected: len,
+                found: self.data.len(),
+            });
+        }
+
+        let num_tiles = self.data.iter().filter(|cell| cell.is_some()).count();
+        if num_tiles == len {
+            return Err(Error::NotPermutation("no blank".to_string()));
+        }
+        let mut seen = vec![false; num_tiles];
+        for cell in self.data.iter() {
+            match *cell {
+                None => {}
+                Some(n) if n < 0 || n as usize >= num_tiles => {
+                    return Err(Error::NotPermutation(format!("tile {} out of range", n)))
+                }
+                Some(n) if seen[n as usize] => {
+                    return Err(Error::NotPermutation(format!("tile {} repeated", n)))
+                }
+                Some(n) => seen[n as usize] = true,
+            }
         }
+        let blank = self.first_blank() as usize;
+        if blank as i32 != self.empty_idx {
+            return Err(Error::BadEmptyIdx {
+                empty_idx: self.empty_idx,
+                blank,
+            });
+        }
+        Ok(())
+    }
+
+    // Check that both grids are valid and of the same puzzle,
+    // as the searches and heuristics assume.
+    pub fn check_pair(&self, other: &Grid) -> Result<()> {
+        self.validate()?;
+        other.validate()?;
+        if (self.rows, self.cols) != (other.rows, other.cols) {
+            return Err(Error::Mismatch(format!(
+                "{}x{} vs {}x{}",
+                self.rows, self.cols, other.rows, other.cols
+            )));
+        }
+        if self.blanks().count() != other.blanks().count() {
+            return Err(Error::Mismatch("number of blanks".to_string()));
+        }
+        if self.cost != other.cost {
+            return Err(Error::Mismatch(format!(
+                "{:?} vs {:?} tile costs",
+                self.cost, other.cost
+            )));
+        }
+        Ok(())
     }
 
     pub fn rand(size: i32) -> Self {
+        Grid::rand_rect(size, size)
+    }
+
+    pub fn rand_rect(rows: i32, cols: i32) -> Self {
         let mut rng = rand::thread_rng();
-        let empty_idx = rng.gen_range(0..size.pow(2));
-        let mut nums: Vec<i32> = (0..size.pow(2) - 1).collect();
+        let empty_idx = rng.gen_range(0..rows * cols);
+        let mut nums: Vec<i32> = (0..rows * cols - 1).collect();
         let mut data = vec![];
-        for i in 0..size.pow(2) {
+        for i in 0..rows * cols {
             if i == empty_idx {
                 data.push(None);
             } else {
@@ -41,19 +440,19 @@ impl Grid {
                 data.push(Some(num))
             }
         }
-        Grid {
-            data,
-            size,
-            empty_idx,
-        }
+        Grid::new_rect(data, rows, cols, empty_idx)
     }
 
     pub fn rand_with_seed(size: i32, seed: u64) -> Self {
+        Grid::rand_rect_with_seed(size, size, seed)
+    }
+
+    pub fn rand_rect_with_seed(rows: i32, cols: i32, seed: u64) -> Self {
         let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
-        let empty_idx = rng.gen_range(0..size.pow(2));
-        let mut nums: Vec<i32> = (0..size.pow(2) - 1).collect();
+        let empty_idx = rng.gen_range(0..rows * cols);
+        let mut nums: Vec<i32> = (0..rows * cols - 1).collect();
         let mut data = vec![];
-        for i in 0..size.pow(2) {
+        for i in 0..rows * cols {
             if i == empty_idx {
                 data.push(None);
             } else {
@@ -61,53 +460,92 @@ impl Grid {
                 data.push(Some(num))
             }
         }
-        Grid {
-            data,
-            size,
-            empty_idx,
+        Grid::new_rect(data, rows, cols, empty_idx)
+    }
+
+    // One line form "1 2 3/4 _ 5/6 0 7", parsed back by FromStr.
+    pub fn to_compact(&self) -> String {
+        self.data
+            .chunks(self.cols as usize)
+            .map(|row| {
+                row.iter()
+                    .map(|cell| match cell {
+                        Some(num) => num.to_string(),
+                        None => "_".to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    pub fn get_coord(&self, idx: i32) -> Result<(i32, i32)> {
+        let len = self.len();
+        if idx < 0 || idx >= len {
+            return Err(Error::OutOfBounds { idx, len });
         }
+        Ok(self.coord(idx))
     }
 
-    pub fn get_coord(&self, idx: i32) -> (i32, i32) {
-        assert!(idx < self.size.pow(2));
-        let x = idx / self.size;
-        let y = idx % self.size;
-        (x, y)
+    // get_coord for indices known to be on the grid
+    fn coord(&self, idx: i32) -> (i32, i32) {
+        (idx / self.cols, idx % self.cols)
     }
 
+    // Actions move the first blank, see do_move for the others.
     pub fn valid_action(&self, action: (i32, i32)) -> bool {
-        let (mut x, mut y) = self.get_coord(self.empty_idx);
-        x += action.0;
-        y += action.1;
-        if x >= 0 && x < self.size && y >= 0 && y < self.size {
-            return true;
-        }
-        false
+        self.target(self.empty_idx, action).is_some()
+    }
+
+    pub fn do_action(&self, action: (i32, i32)) -> Result<Self> {
+        self.do_move(self.empty_idx, action)
     }
 
-    pub fn do_action(&self, action: (i32, i32)) -> Self {
-        // Given a valid action, return a new grid
+    pub fn try_action(&self, action: (i32, i32)) -> Option<Self> {
+        self.do_action(action).ok()
+    }
+
+    // Move the blank at index blank, returning the new grid and
+    // the cost of the move.
+    pub fn do_move_with_cost(&self, blank: i32, action: (i32, i32)) -> Result<(Self, i32)> {
+        let target = match self.target(blank, action) {
+            Some(target) if self.data.get(blank as usize) == Some(&None) => target,
+            _ => return Err(Error::InvalidAction(action)),
+        };
         let mut data = self.data.clone();
-        let (mut x, mut y) = self.get_coord(self.empty_idx);
-        x += action.0;
-        y += action.1;
-        let empty_idx = x * self.size + y;
-        assert!(empty_idx >= 0 && empty_idx < self.size.pow(2));
-        data[self.empty_idx as usize] = data[empty_idx as usize];
-        data[empty_idx as usize] = None;
-        Grid {
+        let tile = data[target as usize].take();
+        data[blank as usize] = tile;
+        // Only a tile moved, so the first blank changes only if it was moved.
+        let empty_idx = if blank == self.empty_idx {
+            data.iter().position(|cell| cell.is_none()).unwrap() as i32
+        } else {
+            self.empty_idx.min(target)
+        };
+        let grid = Grid {
             data,
-            size: self.size,
+            rows: self.rows,
+            cols: self.cols,
             empty_idx,
-        }
+            cost: self.cost,
+        };
+        Ok((grid, self.cost.of(tile.unwrap())))
     }
 
-    pub fn try_action(&self, action: (i32, i32)) -> Option<Self> {
-        if self.valid_action(action) {
-            return Some(self.do_action(action));
-        } else {
-            None
+    pub fn do_move(&self, blank: i32, action: (i32, i32)) -> Result<Self> {
+        self.do_move_with_cost(blank, action).map(|(grid, _)| grid)
+    }
+
+    // The cell a blank moves to, which must hold a tile.
+    fn target(&self, blank: i32, action: (i32, i32)) -> Option<i32> {
+        let (mut x, mut y) = self.coord(blank);
+        x += action.0;
+        y += action.1;
+        if x >= 0 && x < self.rows && y >= 0 && y < self.cols {
+            let target = x * self.cols + y;
+            return self.data[target as usize].map(|_| target);
         }
+        None
     }
 
     pub fn do_actions(&self, actions: Vec<(i32, i32)>) -> Self {
@@ -122,42 +560,154 @@ impl Grid {
     pub fn rand_actions(&self, n: i32) -> Self {
         // let mut r = rand::rngs::StdRng::seed_from_u64(10);
         let mut r = rand::thread_rng();
-        let moves = vec![(0, 1), (0, -1), (1, 0), (-1, 0)];
+        let moves = [(0, 1), (0, -1), (1, 0), (-1, 0)];
         let actions: Vec<(i32, i32)> = (0..n).map(|_| moves[r.gen_range(0..4)]).collect();
         self.do_actions(actions)
     }
 
     pub fn rand_actions_with_seed(&self, n: i32, s: u64) -> Self {
         let mut r = rand::rngs::StdRng::seed_from_u64(s);
-        let moves = vec![(0, 1), (0, -1), (1, 0), (-1, 0)];
+        let moves = [(0, 1), (0, -1), (1, 0), (-1, 0)];
         let actions: Vec<(i32, i32)> = (0..n).map(|_| moves[r.gen_range(0..4)]).collect();
         self.do_actions(actions)
     }
 
     pub fn expand(&self) -> Vec<Self> {
-        let moves = [(-1, 0), (1, 0), (0, -1), (0, 1)];
-        moves
-            .iter()
-            .filter_map(|&action| self.try_action(action))
+        self.expand_with_cost()
+            .into_iter()
+            .map(|(grid, _)| grid)
             .collect()
     }
 
-    pub fn hash_with<T: StateHash>(&self, hasher: &T) -> u32 {
-        (0..self.size.pow(2))
+    // Successors of all blanks with the cost of the move.
+    pub fn expand_with_cost(&self) -> Vec<(Self, i32)> {
+        self.blanks()
+            .flat_map(|blank| {
+                MOVES
+                    .iter()
+                    .filter_map(move |&action| self.do_move_with_cost(blank, action).ok())
+            })
+            .collect()
+    }
+
+    // expand_with_cost with the folded hashes of the successors, given
+    // the one of self. A move takes one tile from one cell to another,
+    // so only the keys of those two cells change.
+    pub fn expand_hashed<T: StateHash>(&self, hasher: &T, hash: u64) -> Vec<(Self, i32, u64)> {
+        self.blanks()
+            .flat_map(|blank| {
+                MOVES.iter().filter_map(move |&action| {
+                    let target = self.target(blank, action)?;
+                    let (grid, cost) = self.do_move_with_cost(blank, action).ok()?;
+                    let tile = grid.data[blank as usize]?;
+                    let moved = hasher.hash_prop(tile, target) ^ hasher.hash_prop(tile, blank);
+                    let hash = hash ^ moved.fold();
+                    Some((grid, cost, hash))
+                })
+            })
+            .collect()
+    }
+
+    // The folded hash of next, one move from self, given the one of self.
+    // The move is found from the first blanks when it moved the first
+    // blank of self, by comparing the boards otherwise.
+    pub fn successor_hash<T: StateHash>(&self, hasher: &T, hash: u64, next: &Grid) -> u64 {
+        let cell = |grid: &Grid, i: i32| grid.data.get(i as usize).copied().flatten();
+        let (from, to) = (next.empty_idx, self.empty_idx);
+        let cells = match (cell(self, from), cell(next, to)) {
+            (Some(a), Some(b)) if a == b && cell(next, from).is_none() => Some((from, to)),
+            _ => {
+                let diff: Vec<i32> = (0..self.len().min(next.len()))
+                    .filter(|&i| self.data[i as usize] != next.data[i as usize])
+                    .collect();
+                match diff[..] {
+                    [a, b] if cell(next, a).is_none() => Some((a, b)),
+                    [a, b] => Some((b, a)),
+                    _ => None,
+                }
+            }
+        };
+        match cells.and_then(|(from, to)| Some((from, to, cell(next, to)?))) {
+            Some((from, to, tile)) => {
+                hash ^ (hasher.hash_prop(tile, from) ^ hasher.hash_prop(tile, to)).fold()
+            }
+            None => next.hash_with(hasher).fold(),
+        }
+    }
+
+    pub fn hash_with<T: StateHash>(&self, hasher: &T) -> T::Key {
+        (0..self.len())
             .filter(|&i| self.data[i as usize].is_some())
             .map(|i| {
                 let n = self.data[i as usize].unwrap();
                 hasher.hash_prop(n, i)
             })
-            .reduce(|acc, e| acc ^ e)
-            .unwrap()
+            .fold(T::Key::default(), |acc, e| acc ^ e)
+    }
+
+    // A dense index of boards with one blank in 0..rows * cols!,
+    // for up to 20 cells.
+    pub fn rank(&self, ranking: Ranking) -> Result<u64> {
+        let perm = grid_perm(self)?;
+        if perm.len() > MAX_PERM {
+            return Err(Error::TooLarge(format!(
+                "{} cells, ranks fit {}",
+                perm.len(),
+                MAX_PERM
+            )));
+        }
+        Ok(ranking.rank(&perm))
+    }
+
+    // The board of a rank, with unit costs.
+    pub fn unrank(rank: u64, rows: i32, cols: i32, ranking: Ranking) -> Result<Self> {
+        let n = (rows * cols).max(0) as usize;
+        if n > MAX_PERM {
+            return Err(Error::TooLarge(format!(
+                "{} cells, ranks fit {}",
+                n, MAX_PERM
+            )));
+        }
+        if rows < 1 || cols < 1 || rank >= count_partial(n, n) {
+            return Err(Error::OutOfBounds {
+                idx: rank.min(i32::MAX as u64) as i32,
+                len: count_partial(n, n).min(i32::MAX as u64) as i32,
+            });
+        }
+        let mut perm = vec![0; n];
+        ranking.unrank(rank, &mut perm);
+        let data: Vec<Option<i32>> = perm
+            .iter()
+            .map(|&v| (v as usize != n - 1).then_some(v as i32))
+            .collect();
+        let empty_idx = perm.iter().position(|&v| v as usize == n - 1).unwrap() as i32;
+        Ok(Grid::new_rect(data, rows, cols, empty_idx))
+    }
+
+    // A dense index of the cells of some tiles in
+    // 0..count_partial(rows * cols, tiles.len()).
+    pub fn rank_tiles(&self, tiles: &[i32], ranking: Ranking) -> Result<u64> {
+        let perm = grid_perm(self)?;
+        let mut seq = Vec::with_capacity(tiles.len());
+        for (j, &t) in tiles.iter().enumerate() {
+            match self.data.iter().position(|&cell| cell == Some(t)) {
+                Some(i) if !tiles[..j].contains(&t) => seq.push(i as u8),
+                _ => {
+                    return Err(Error::NotPermutation(format!(
+                        "{:?} are not distinct tiles",
+                        tiles
+                    )))
+                }
+            }
+        }
+        Ok(ranking.rank_partial(&seq, perm.len()))
     }
 }
 
 impl Display for Grid {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for i in 0..self.size.pow(2) {
-            if i % self.size == 0 {
+        for i in 0..self.len() {
+            if i % self.cols == 0 {
                 if i == 0 {
                     write!(f, "[")?;
                 } else {
@@ -168,77 +718,133 @@ impl Display for Grid {
                 Some(num) => write!(f, "{:>5}", num)?,
                 _ => write!(f, "{:>5}", "-")?,
             }
-            if i % self.size == self.size - 1 {
+            if i % self.cols == self.cols - 1 {
                 write!(f, "]")?;
             }
         }
-        write!(f, "\n")?;
+        writeln!(f)?;
         Ok(())
     }
 }
 
-impl PartialEq for Grid {
-    fn eq(&self, other: &Self) -> bool {
-        assert_eq!(self.size, other.size);
-        (0..self.size.pow(2)).all(|i| self.data[i as usize] == other.data[i as usize])
+// Parses the layout printed by Display as well as the compact form
+// "1 2 3/4 _ 5/6 0 -". Rows are separated by newlines or '/', cells by
+// whitespace or commas, the blank is written as '-' or '_'.
+// empty_idx is derived from the blank and the result is validated.
+impl FromStr for Grid {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let rows: Vec<Vec<&str>> = s
+            .split(['\n', '/'])
+            .map(|row| {
+                row.trim()
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .split(|c: char| c.is_whitespace() || c == ',')
+                    .filter(|cell| !cell.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|row| !row.is_empty())
+            .collect();
+
+        if rows.is_empty() {
+            return Err(Error::Parse("empty grid".to_string()));
+        }
+        let cols = rows[0].len();
+        let mut data = Vec::with_capacity(rows.len() * cols);
+        for (r, row) in rows.iter().enumerate() {
+            if row.len() != cols {
+                return Err(Error::Parse(format!(
+                    "row {} has {} cells, expected {}",
+                    r,
+                    row.len(),
+                    cols
+                )));
+            }
+            for cell in row {
+                data.push(match *cell {
+                    "-" | "_" => None,
+                    n => Some(
+                        n.parse()
+                            .map_err(|_| Error::Parse(format!("bad cell '{}'", n)))?,
+                    ),
+                });
+            }
+        }
+
+        let empty_idx = data
+            .iter()
+            .position(|cell| cell.is_none())
+            .ok_or_else(|| Error::NotPermutation("no blank".to_string()))?;
+        Grid::try_new_rect(data, rows.len() as i32, cols as i32, empty_idx as i32)
     }
 }
 
-impl Eq for Grid {}
-
 #[derive(Debug, Clone)]
-pub struct Node {
-    pub state: Grid,
-    pub f: i32,
-    pub g: i32,
-    pub h: i32,
+pub struct Node<S: State = Grid> {
+    pub state: S,
+    pub f: S::Cost,
+    pub g: S::Cost,
+    pub h: S::Cost,
+    // Hash of the state under the hasher of the search,
+    // 0 in searches that keep none.
+    pub hash: u64,
+    // Hash of the state under the abstract hasher of ZHDA*, kept up to
+    // date from the parent's, 0 in other searches.
+    pub abstract_hash: u64,
     // pub prev_actions: Box<Vec<(i32, i32)>>,
     // pub prev_node: Option<Box<Node>>,
 }
 
-impl Node {
-    pub fn new(state: Grid) -> Self {
+impl<S: State> Node<S> {
+    pub fn new(state: S) -> Self {
         Node {
             state,
-            f: 0,
-            g: 0,
-            h: 0,
+            f: S::Cost::ZERO,
+            g: S::Cost::ZERO,
+            h: S::Cost::ZERO,
+            hash: 0,
+            abstract_hash: 0,
             // prev_actions: Box::new(vec![]),
             // prev_node: None,
         }
     }
 
-    pub fn calc_cost(&mut self, end_state: &Grid, h_func: fn(&Grid, &Grid) -> i32) {
-        self.h = h_func(&self.state, end_state);
+    pub fn calc_cost<H: Heuristic<S> + ?Sized>(&mut self, end_state: &S, h_func: &H) {
+        self.h = h_func.h(&self.state, end_state);
         self.f = self.g + self.h;
     }
 }
 
-impl PartialEq for Node {
+impl<S: State> PartialEq for Node<S> {
     fn eq(&self, other: &Self) -> bool {
         self.f == other.f
     }
 }
 
-impl Eq for Node {}
+impl<S: State> Eq for Node<S> {}
 
-impl PartialOrd for Node {
+impl<S: State> PartialOrd for Node<S> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        other.f.partial_cmp(&self.f)
+        Some(self.cmp(other))
     }
 }
 
-impl Ord for Node {
+impl<S: State> Ord for Node<S> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         other.f.cmp(&self.f)
     }
 }
 
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Log {
     pub iter_cnt: i32,
-    pub node_cnt: i32,
+    pub node_cnt: i32, // nodes sent to other threads
     pub abort_cnt: i32,
     // pub com_time: i32,
+    pub exp_cnt: i32, // nodes expanded
+    pub gen_cnt: i32, // successors generated
 }
 
 impl Log {
@@ -247,39 +853,230 @@ impl Log {
             iter_cnt: 0,
             node_cnt: 0,
             abort_cnt: 0,
+            exp_cnt: 0,
+            gen_cnt: 0,
         }
     }
 
-    pub fn merge(&mut self, log: Log) -> () {
+    pub fn merge(&mut self, log: Log) {
         self.iter_cnt += log.iter_cnt;
         self.abort_cnt += log.abort_cnt;
-        self.node_cnt += log.abort_cnt;
+        self.node_cnt += log.node_cnt;
+        self.exp_cnt += log.exp_cnt;
+        self.gen_cnt += log.gen_cnt;
+    }
+}
+
+// Search budget shared by all threads of one search.
+pub struct Limit {
+    max_exp: u64,
+    deadline: Option<Instant>,
+    exp: AtomicU64,
+    hit: AtomicBool,
+}
+
+impl Limit {
+    pub fn new(max_exp: Option<u64>, timeout: Option<Duration>) -> Self {
+        Limit {
+            max_exp: max_exp.unwrap_or(u64::MAX),
+            deadline: timeout.map(|t| Instant::now() + t),
+            exp: AtomicU64::new(0),
+            hit: AtomicBool::new(false),
+        }
+    }
+
+    pub fn none() -> Self {
+        Limit::new(None, None)
+    }
+
+    // Count one expansion, true once the budget is exhausted.
+    pub fn tick(&self) -> bool {
+        self.exp.fetch_add(1, Ordering::Relaxed);
+        self.reached()
+    }
+
+    pub fn reached(&self) -> bool {
+        if self.hit.load(Ordering::Relaxed) {
+            return true;
+        }
+        let reached = self.exp.load(Ordering::Relaxed) >= self.max_exp
+            || self.deadline.is_some_and(|d| Instant::now() >= d);
+        if reached {
+            self.hit.store(true, Ordering::Relaxed);
+        }
+        reached
+    }
+}
+
+// Keys of the Zobrist hashers. Searches keep 64 bits of a key,
+// wider keys are folded to 64.
+pub trait ZKey:
+    Copy + Eq + Hash + Debug + Default + BitXor<Output = Self> + Send + Sync + 'static
+{
+    const BITS: u32;
+    fn random(r: &mut StdRng) -> Self;
+    fn fold(self) -> u64;
+}
+
+impl ZKey for u32 {
+    const BITS: u32 = 32;
+
+    fn random(r: &mut StdRng) -> Self {
+        r.gen()
+    }
+
+    fn fold(self) -> u64 {
+        self as u64
+    }
+}
+
+impl ZKey for u64 {
+    const BITS: u32 = 64;
+
+    fn random(r: &mut StdRng) -> Self {
+        r.gen()
+    }
+
+    fn fold(self) -> u64 {
+        self
+    }
+}
+
+impl ZKey for u128 {
+    const BITS: u32 = 128;
+
+    fn random(r: &mut StdRng) -> Self {
+        r.gen()
+    }
+
+    fn fold(self) -> u64 {
+        self as u64 ^ (self >> 64) as u64
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyWidth {
+    #[default]
+    Bits32,
+    Bits64,
+    Bits128,
+}
+
+impl KeyWidth {
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            32 => Some(KeyWidth::Bits32),
+            64 => Some(KeyWidth::Bits64),
+            128 => Some(KeyWidth::Bits128),
+            _ => None,
+        }
+    }
+
+    pub fn bits(&self) -> u32 {
+        match self {
+            KeyWidth::Bits32 => 32,
+            KeyWidth::Bits64 => 64,
+            KeyWidth::Bits128 => 128,
+        }
+    }
+}
+
+// The Zobrist keys a search hashes with, see State::keyed_hasher.
+// Without a seed the hashers are seeded like ZHasher::new and
+// AZHasher::new, the default keys hash like those.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Keys {
+    pub width: KeyWidth,
+    pub seed: Option<u64>,
+}
+
+// A hasher whose type is picked at run time.
+pub struct AnyHasher<S: State>(Arc<dyn DynHasher<S>>);
+
+// StateHasher without Clone, which boxed hashers cannot be.
+trait DynHasher<S: State>: Send + Sync {
+    fn hash(&self, state: &S) -> u64;
+    fn hashed_successors(&self, state: &S, hash: u64) -> Vec<(S, S::Cost, u64)>;
+    fn successor_hash(&self, state: &S, hash: u64, succ: &S) -> u64;
+}
+
+impl<S: State, T: StateHasher<S>> DynHasher<S> for T {
+    fn hash(&self, state: &S) -> u64 {
+        StateHasher::hash(self, state)
+    }
+
+    fn hashed_successors(&self, state: &S, hash: u64) -> Vec<(S, S::Cost, u64)> {
+        StateHasher::hashed_successors(self, state, hash)
+    }
+
+    fn successor_hash(&self, state: &S, hash: u64, succ: &S) -> u64 {
+        StateHasher::successor_hash(self, state, hash, succ)
+    }
+}
+
+impl<S: State> AnyHasher<S> {
+    pub fn new<T: StateHasher<S>>(hasher: T) -> Self {
+        AnyHasher(Arc::new(hasher))
+    }
+}
+
+impl<S: State> Clone for AnyHasher<S> {
+    fn clone(&self) -> Self {
+        AnyHasher(self.0.clone())
+    }
+}
+
+impl<S: State> StateHasher<S> for AnyHasher<S> {
+    fn hash(&self, state: &S) -> u64 {
+        self.0.hash(state)
+    }
+
+    fn hashed_successors(&self, state: &S, hash: u64) -> Vec<(S, S::Cost, u64)> {
+        self.0.hashed_successors(state, hash)
+    }
+
+    fn successor_hash(&self, state: &S, hash: u64, succ: &S) -> u64 {
+        self.0.successor_hash(state, hash, succ)
     }
 }
 
 pub trait StateHash {
+    type Key: ZKey;
     // fn hash_grid(&self, g: &Grid) -> u32;
     // fn get_htable(&self) -> &Vec<Vec<u32>>;
-    fn hash_prop(&self, _: i32, _: i32) -> u32;
+    fn hash_prop(&self, _: i32, _: i32) -> Self::Key;
 }
 
+// Seeds of ZHasher::new and AZHasher::new.
+pub const Z_SEED: u64 = 420;
+pub const AZ_SEED: u64 = 100;
+
+// Keys are u32 unless asked otherwise, ZHasher::<u64>::with_seed
+// gives wider ones.
 #[derive(Debug, Clone)]
-pub struct ZHasher {
+pub struct ZHasher<K = u32> {
     // num[loc[bits]]
-    pub htable: Vec<Vec<u32>>,
+    pub htable: Vec<Vec<K>>,
 }
 
 impl ZHasher {
-    pub fn new(size: i32) -> Self {
-        let mut r = rand::rngs::StdRng::seed_from_u64(420);
-        let htable: Vec<Vec<u32>> = (0..size.pow(2) - 1)
-            .map(|_| (0..size.pow(2)).map(|_| r.gen()).collect())
+    pub fn new(rows: i32, cols: i32) -> Self {
+        ZHasher::with_seed(rows, cols, Z_SEED)
+    }
+}
+
+impl<K: ZKey> ZHasher<K> {
+    pub fn with_seed(rows: i32, cols: i32, seed: u64) -> Self {
+        let mut r = StdRng::seed_from_u64(seed);
+        let htable: Vec<Vec<K>> = (0..rows * cols - 1)
+            .map(|_| (0..rows * cols).map(|_| K::random(&mut r)).collect())
             .collect();
         ZHasher { htable }
     }
 }
 
-impl StateHash for ZHasher {
+impl<K: ZKey> StateHash for ZHasher<K> {
+    type Key = K;
     // fn hash_grid(&self, g: &Grid) -> u32 {
     //     let hval = 0;
     //     (0..g.size.pow(2))
@@ -296,35 +1093,229 @@ impl StateHash for ZHasher {
     //     &self.htable
     // }
 
-    fn hash_prop(&self, n: i32, i: i32) -> u32 {
+    fn hash_prop(&self, n: i32, i: i32) -> K {
         // Hash one propsition
         self.htable[n as usize][i as usize]
     }
 }
 
 #[derive(Debug, Clone)]
-pub struct AZHasher {
-    pub htable: Vec<Vec<u32>>,
-    pub size: i32,
+pub struct AZHasher<K = u32> {
+    pub htable: Vec<Vec<K>>,
+    pub cols: i32,
 }
 
 impl AZHasher {
-    // This implementation uses a hand-craft abstract function.
-    pub fn new(size: i32) -> Self {
-        let mut r = rand::rngs::StdRng::seed_from_u64(100);
-        let htable: Vec<Vec<u32>> = (0..size.pow(2) - 1)
-            .map(|_| (0..size).map(|_| r.gen()).collect())
+    // This implementation uses a hand-craft abstract function,
+    // a tile is abstracted to the row it is in.
+    pub fn new(rows: i32, cols: i32) -> Self {
+        AZHasher::with_seed(rows, cols, AZ_SEED)
+    }
+}
+
+impl<K: ZKey> AZHasher<K> {
+    pub fn with_seed(rows: i32, cols: i32, seed: u64) -> Self {
+        let mut r = StdRng::seed_from_u64(seed);
+        let htable: Vec<Vec<K>> = (0..rows * cols - 1)
+            .map(|_| (0..rows).map(|_| K::random(&mut r)).collect())
             .collect();
-        AZHasher { htable, size }
+        AZHasher { htable, cols }
     }
 
     pub fn abx(&self, i: i32) -> i32 {
-        i / self.size
+        i / self.cols
     }
 }
 
-impl StateHash for AZHasher {
-    fn hash_prop(&self, n: i32, i: i32) -> u32 {
+impl<K: ZKey> StateHash for AZHasher<K> {
+    type Key = K;
+
+    fn hash_prop(&self, n: i32, i: i32) -> K {
         self.htable[n as usize][self.abx(i) as usize]
     }
 }
+
+impl<T: StateHash + Clone + Send + Sync + 'static> StateHasher<Grid> for T {
+    fn hash(&self, state: &Grid) -> u64 {
+        state.hash_with(self).fold()
+    }
+
+    fn hashed_successors(&self, state: &Grid, hash: u64) -> Vec<(Grid, i32, u64)> {
+        state.expand_hashed(self, hash)
+    }
+
+    fn successor_hash(&self, state: &Grid, hash: u64, succ: &Grid) -> u64 {
+        state.successor_hash(self, hash, succ)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grids() -> Vec<Grid> {
+        let mut grids = Vec::new();
+        for (rows, cols) in [(1, 2), (2, 2), (3, 3), (2, 5), (4, 4), (5, 3)] {
+            for seed in 0..5 {
+                grids.push(Grid::rand_rect_with_seed(rows, cols, seed));
+            }
+        }
+        grids
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for grid in grids() {
+            assert_eq!(grid.to_string().parse::<Grid>().unwrap(), grid);
+        }
+    }
+
+    #[test]
+    fn compact_round_trip() {
+        for grid in grids() {
+            let compact = grid.to_compact();
+            assert!(!compact.contains('\n'));
+            assert_eq!(compact.parse::<Grid>().unwrap(), grid);
+        }
+    }
+
+    #[test]
+    fn parse_forms() {
+        let grid: Grid = "1 2 3/4 _ 5/6 0 7".parse().unwrap();
+        assert_eq!(grid.empty_idx, 4);
+        assert_eq!(
+            "[1, 2, 3]\n[4, -, 5]\n[6, 0, 7]".parse::<Grid>().unwrap(),
+            grid
+        );
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        for grid in grids() {
+            for grid in [grid.clone(), grid.with_cost(TileCost::Heavy)] {
+                let mut bytes = vec![];
+                grid.encode(&mut bytes);
+                bytes.extend([7, 7]);
+                let (decoded, used) = Grid::decode(&bytes).unwrap();
+                assert_eq!(decoded, grid);
+                assert_eq!(decoded.cost, grid.cost);
+                assert_eq!(used, bytes.len() - 2);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        let grid = Grid::rand_rect_with_seed(3, 4, 0);
+        let mut bytes = vec![];
+        grid.encode(&mut bytes);
+        for len in 0..bytes.len() {
+            assert!(Grid::decode(&bytes[..len]).is_err(), "{} bytes", len);
+        }
+        // unknown tile cost
+        let mut bad = bytes.clone();
+        bad[4] = 2;
+        assert!(Grid::decode(&bad).is_err());
+        // a size far beyond the bytes sent
+        let mut bad = bytes.clone();
+        bad[..4].copy_from_slice(&[0xff; 4]);
+        assert!(Grid::decode(&bad).is_err());
+        // not a permutation
+        let mut bad = vec![];
+        let data = vec![Some(0), Some(0), Some(1), None];
+        Grid::new_rect(data, 2, 2, 3).encode(&mut bad);
+        assert!(Grid::decode(&bad).is_err());
+    }
+
+    // Every move has a way back: the move with the back id of the
+    // successor returns to the board at the same cost.
+    #[test]
+    fn multi_blank_back_moves() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for (rows, cols, blanks) in [(3, 3, 1), (3, 3, 2), (2, 4, 3), (4, 4, 4), (1, 5, 2)] {
+            for cost in [TileCost::Unit, TileCost::Heavy] {
+                let mut grid = Grid::rand_rect_with_seed(rows, cols, 0)
+                    .with_cost(cost)
+                    .with_blanks(blanks);
+                assert_eq!(grid.blanks().count(), blanks as usize);
+                for _ in 0..50 {
+                    let moves = grid.moves();
+                    let mut ids: Vec<u8> = moves.iter().map(|m| m.2).collect();
+                    ids.sort_unstable();
+                    ids.dedup();
+                    assert_eq!(ids.len(), moves.len());
+                    assert_eq!(moves.len(), grid.successors().len());
+                    for (next, cost, _, back) in moves.iter() {
+                        let back = back.unwrap();
+                        let (prev, c, _, _) =
+                            next.moves().into_iter().find(|m| m.2 == back).unwrap();
+                        assert_eq!(prev, grid);
+                        assert_eq!(c, *cost);
+                    }
+                    grid = moves[rng.gen_range(0..moves.len())].0.clone();
+                    grid.validate().unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_moves() {
+        let grid = Grid::rand_rect_with_seed(2, 3, 0).with_blanks(2);
+        let blanks: Vec<i32> = grid.blanks().collect();
+        for tile in (0..6).filter(|i| !blanks.contains(i)) {
+            for action in MOVES {
+                assert!(matches!(
+                    grid.do_move(tile, action),
+                    Err(Error::InvalidAction(_))
+                ));
+            }
+        }
+        for blank in [-1, 6, 100] {
+            assert!(grid.do_move(blank, (0, 1)).is_err());
+        }
+    }
+
+    // Keys updated move by move are the keys of the boards.
+    fn check_keys<T: StateHash>(hasher: &T, grid: &Grid) {
+        let hash = grid.hash_with(hasher).fold();
+        let succs = grid.expand_hashed(hasher, hash);
+        assert_eq!(succs.len(), grid.successors().len());
+        for (next, _, key) in succs {
+            let full = next.hash_with(hasher).fold();
+            assert_eq!(key, full, "{} to {}", grid, next);
+            assert_eq!(grid.successor_hash(hasher, hash, &next), full);
+        }
+    }
+
+    fn check_widths<K: ZKey>() {
+        for (rows, cols, blanks) in [(3, 3, 1), (2, 5, 1), (4, 4, 1), (3, 3, 2), (4, 4, 3)] {
+            let z = ZHasher::<K>::with_seed(rows, cols, 5);
+            let az = AZHasher::<K>::with_seed(rows, cols, 5);
+            for cost in [TileCost::Unit, TileCost::Heavy] {
+                let end = Grid::rand_rect_with_seed(rows, cols, 1)
+                    .with_cost(cost)
+                    .with_blanks(blanks);
+                for seed in 0..5 {
+                    let grid = end.rand_actions_with_seed(30, seed);
+                    check_keys(&z, &grid);
+                    check_keys(&az, &grid);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_keys() {
+        check_widths::<u32>();
+        check_widths::<u64>();
+        check_widths::<u128>();
+    }
+
+    #[test]
+    fn parse_errors() {
+        for s in ["", "1 2/3", "1 2/x _", "1 2/3 0", "1 1/_ 0", "_ 2/3 4"] {
+            assert!(s.parse::<Grid>().is_err(), "{:?}", s);
+        }
+    }
+}
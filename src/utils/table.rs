@@ -0,0 +1,127 @@
+// Maps from states to values for searches that keep the hash of every
+// state in its node, so a lookup does not hash the state again.
+// Entries are found by hash first and compared in full after, a state
+// whose hash is taken by another state goes to a second map keyed by
+// the state itself.
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+
+// Passes a u64 hash on, mixed so that its high bits depend on all bits.
+#[derive(Default)]
+pub struct PassHasher(u64);
+
+impl Hasher for PassHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(0x100_0000_01b3);
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.0 = (n ^ n >> 32).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StateMap<S, V> {
+    main: HashMap<u64, (S, V), BuildHasherDefault<PassHasher>>,
+    spill: HashMap<S, V>,
+}
+
+impl<S: std::hash::Hash + Eq, V> Default for StateMap<S, V> {
+    fn default() -> Self {
+        StateMap::new()
+    }
+}
+
+impl<S: std::hash::Hash + Eq, V> StateMap<S, V> {
+    pub fn new() -> Self {
+        StateMap {
+            main: HashMap::default(),
+            spill: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.main.len() + self.spill.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.main.is_empty() && self.spill.is_empty()
+    }
+
+    pub fn get(&self, hash: u64, state: &S) -> Option<&V> {
+        match self.main.get(&hash) {
+            Some((s, v)) if s == state => Some(v),
+            _ if self.spill.is_empty() => None,
+            _ => self.spill.get(state),
+        }
+    }
+
+    pub fn contains(&self, hash: u64, state: &S) -> bool {
+        self.get(hash, state).is_some()
+    }
+
+    // Returns the value the state had.
+    pub fn insert(&mut self, hash: u64, state: S, value: V) -> Option<V> {
+        match self.main.get_mut(&hash) {
+            Some((s, v)) if *s == state => Some(std::mem::replace(v, value)),
+            Some(_) => self.spill.insert(state, value),
+            None => {
+                let old = if self.spill.is_empty() {
+                    None
+                } else {
+                    self.spill.remove(&state)
+                };
+                self.main.insert(hash, (state, value));
+                old
+            }
+        }
+    }
+
+    pub fn remove(&mut self, hash: u64, state: &S) -> Option<V> {
+        match self.main.get(&hash) {
+            Some((s, _)) if s == state => self.main.remove(&hash).map(|(_, v)| v),
+            _ if self.spill.is_empty() => None,
+            _ => self.spill.remove(state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collisions_spill() {
+        let mut map = StateMap::new();
+        assert_eq!(map.insert(1, "a", 1), None);
+        // same hash, other states
+        assert_eq!(map.insert(1, "b", 2), None);
+        assert_eq!(map.insert(1, "c", 3), None);
+        assert_eq!(map.insert(2, "d", 4), None);
+        assert_eq!(map.len(), 4);
+        assert_eq!(map.get(1, &"b"), Some(&2));
+        assert_eq!(map.get(1, &"d"), None);
+        assert_eq!(map.insert(1, "b", 5), Some(2));
+        assert_eq!(map.get(1, &"b"), Some(&5));
+        // the state in the main map goes, the spilled ones stay
+        assert_eq!(map.remove(1, &"a"), Some(1));
+        assert!(!map.contains(1, &"a"));
+        assert_eq!(map.get(1, &"c"), Some(&3));
+        // a spilled state inserted again while its hash is free moves
+        // to the main map, once
+        assert_eq!(map.insert(1, "c", 6), Some(3));
+        assert_eq!(map.get(1, &"c"), Some(&6));
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.remove(1, &"c"), Some(6));
+        assert_eq!(map.remove(1, &"b"), Some(5));
+        assert_eq!(map.remove(1, &"b"), None);
+        assert_eq!(map.remove(2, &"d"), Some(4));
+        assert!(map.is_empty());
+    }
+}
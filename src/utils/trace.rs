@@ -0,0 +1,268 @@
+// Opt-in event tracing of the parallel searches.
+// Every thread records into its own Tracer, the traces are merged
+// after the threads join and written in the Chrome trace-event format,
+// which chrome://tracing and ui.perfetto.dev can open.
+use serde::Serialize;
+use serde_json::json;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Expand,
+    Send,
+    Recv,
+    Idle,
+    Incumbent,
+    Term,
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::Expand => "expand",
+            EventKind::Send => "send",
+            EventKind::Recv => "recv",
+            EventKind::Idle => "idle",
+            EventKind::Incumbent => "incumbent",
+            EventKind::Term => "term",
+        }
+    }
+
+    // Name of the argument stored in Event::arg
+    fn arg_name(&self) -> &'static str {
+        match self {
+            EventKind::Send => "to",
+            _ => "f",
+        }
+    }
+}
+
+// Times are nanoseconds since the start of the search.
+// Events with a non-zero duration are spans, the others instants.
+#[derive(Debug, Clone, Copy)]
+pub struct Event {
+    pub kind: EventKind,
+    pub tid: u32,
+    pub ts: u64,
+    pub dur: u64,
+    pub arg: f64,
+}
+
+pub struct Tracer {
+    enabled: bool,
+    tid: u32,
+    start: Instant,
+    idle_since: Option<u64>,
+    events: Vec<Event>,
+}
+
+impl Tracer {
+    pub fn new(tid: usize, start: Instant) -> Self {
+        Tracer {
+            enabled: true,
+            tid: tid as u32,
+            start,
+            idle_since: None,
+            events: vec![],
+        }
+    }
+
+    // A tracer that records nothing.
+    pub fn off() -> Self {
+        Tracer {
+            enabled: false,
+            tid: 0,
+            start: Instant::now(),
+            idle_since: None,
+            events: vec![],
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn now(&self) -> u64 {
+        if !self.enabled {
+            return 0;
+        }
+        self.start.elapsed().as_nanos() as u64
+    }
+
+    pub fn instant(&mut self, kind: EventKind, arg: f64) {
+        if !self.enabled {
+            return;
+        }
+        let ts = self.now();
+        self.push(kind, ts, 0, arg);
+    }
+
+    // Record a span that began at ts, as returned by now().
+    pub fn span(&mut self, kind: EventKind, ts: u64, arg: f64) {
+        if !self.enabled {
+            return;
+        }
+        let dur = self.now().saturating_sub(ts).max(1);
+        self.push(kind, ts, dur, arg);
+    }
+
+    // Idle spans are opened by idle() and closed by the next busy().
+    pub fn idle(&mut self) {
+        if self.enabled && self.idle_since.is_none() {
+            self.idle_since = Some(self.now());
+        }
+    }
+
+    pub fn busy(&mut self) {
+        if let Some(ts) = self.idle_since.take() {
+            self.span(EventKind::Idle, ts, 0.0);
+        }
+    }
+
+    pub fn finish(mut self) -> Trace {
+        self.busy();
+        if self.enabled {
+            self.instant(EventKind::Term, 0.0);
+        }
+        Trace {
+            events: self.events,
+        }
+    }
+
+    fn push(&mut self, kind: EventKind, ts: u64, dur: u64, arg: f64) {
+        self.events.push(Event {
+            kind,
+            tid: self.tid,
+            ts,
+            dur,
+            arg,
+        });
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    pub events: Vec<Event>,
+}
+
+#[derive(Serialize)]
+struct ChromeEvent {
+    name: &'static str,
+    ph: &'static str,
+    ts: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dur: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    s: Option<&'static str>,
+    pid: u32,
+    tid: u32,
+    args: serde_json::Value,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Trace { events: vec![] }
+    }
+
+    pub fn merge(&mut self, trace: Trace) {
+        self.events.extend(trace.events);
+    }
+
+    pub fn count(&self, kind: EventKind) -> usize {
+        self.events.iter().filter(|e| e.kind == kind).count()
+    }
+
+    pub fn write_chrome<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let mut events: Vec<&Event> = self.events.iter().collect();
+        events.sort_by_key(|e| (e.ts, e.tid));
+
+        let mut w = BufWriter::new(File::create(path)?);
+        write!(w, "{{\"displayTimeUnit\":\"ns\",\"traceEvents\":[")?;
+        for (i, e) in events.iter().enumerate() {
+            let ce = ChromeEvent {
+                name: e.kind.name(),
+                ph: if e.dur > 0 { "X" } else { "i" },
+                ts: e.ts as f64 / 1000.0,
+                dur: (e.dur > 0).then(|| e.dur as f64 / 1000.0),
+                s: (e.dur == 0).then_some("t"),
+                pid: 0,
+                tid: e.tid,
+                args: json!({ e.kind.arg_name(): e.arg }),
+            };
+            if i > 0 {
+                write!(w, ",")?;
+            }
+            serde_json::to_writer(&mut w, &ce)?;
+        }
+        write!(w, "]}}")?;
+        w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::astar::{dpa, hda};
+    use crate::utils::helpers::weighted_man_dist;
+    use crate::utils::structs::{Grid, ZHasher};
+    use std::collections::HashMap;
+
+    // The file parses, and per thread the expansions follow one another
+    // without overlapping, as begin and end events would pair up.
+    fn check_chrome(trace: &Trace, name: &str) {
+        let path =
+            std::env::temp_dir().join(format!("astar-trace-{}-{}.json", std::process::id(), name));
+        trace.write_chrome(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        let events = json["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), trace.events.len());
+
+        let mut ends: HashMap<u64, f64> = HashMap::new();
+        for e in events {
+            let tid = e["tid"].as_u64().unwrap();
+            let ts = e["ts"].as_f64().unwrap();
+            match e["ph"].as_str().unwrap() {
+                "X" => {
+                    let dur = e["dur"].as_f64().unwrap();
+                    assert!(dur > 0.0);
+                    if e["name"] == "expand" {
+                        let end = ends.entry(tid).or_insert(0.0);
+                        assert!(ts >= *end, "overlapping expansions on {}", tid);
+                        *end = ts + dur;
+                    }
+                }
+                "i" => assert_eq!(e["s"], "t"),
+                ph => panic!("unknown phase {}", ph),
+            }
+        }
+        // one term event per thread, after all of its spans
+        for (tid, end) in ends {
+            let term = events
+                .iter()
+                .find(|e| e["name"] == "term" && e["tid"].as_u64() == Some(tid))
+                .unwrap();
+            assert!(term["ts"].as_f64().unwrap() >= end);
+        }
+    }
+
+    #[test]
+    fn chrome_traces() {
+        let end = Grid::rand_with_seed(4, 69);
+        let start = end.rand_actions_with_seed(40, 0);
+        let (node, trace) = dpa::astar_traced(&start, &end, weighted_man_dist, 3, 0.5);
+        assert!(node.is_some());
+        assert_eq!(trace.count(EventKind::Term), 3);
+        assert!(trace.count(EventKind::Expand) > 0);
+        check_chrome(&trace, "dpa");
+
+        let hasher = ZHasher::<u32>::new(4, 4);
+        let (node, trace) = hda::astar_traced(&start, &end, weighted_man_dist, 3, hasher);
+        assert!(node.is_some());
+        check_chrome(&trace, "hda");
+    }
+}